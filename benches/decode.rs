@@ -0,0 +1,75 @@
+use std::{
+    env::args,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use msp430_disassembler::{decoder::decode, disassemble};
+
+/*
+    Linear sweep throughput, `cargo bench` or `cargo bench -- some.bin`.
+    The image is repeated until it's a few megabytes, like the firmware
+    archives this gets pointed at, then swept with just the decoder and
+    again building listing lines. Each is timed a few times and the best
+    run counts, it's the least disturbed by whatever else the machine was
+    doing.
+*/
+
+const SIZE: usize = 4 << 20;
+const RUNS: usize = 5;
+
+fn best<F: FnMut() -> usize>(mut sweep: F) -> (usize, Duration) {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let count = black_box(sweep());
+            (count, start.elapsed())
+        })
+        .min_by_key(|&(_, time)| time)
+        .unwrap()
+}
+
+fn report(name: &str, bytes: usize, (count, time): (usize, Duration)) {
+    let seconds = time.as_secs_f64();
+    println!(
+        "{name:<12} {count} instructions in {:.1} ms, {:.1}M instructions/s, {:.1} MB/s",
+        seconds * 1e3,
+        count as f64 / seconds / 1e6,
+        bytes as f64 / seconds / 1e6
+    );
+}
+
+fn main() {
+    let path = args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "output.bin".to_owned());
+    let mut image = std::fs::read(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+    image.truncate(image.len() & !1);
+    assert!(!image.is_empty(), "{path} has no instructions in it");
+    let bytes: Vec<u8> = image
+        .iter()
+        .copied()
+        .cycle()
+        .take(SIZE.max(image.len()))
+        .collect();
+    println!("{path}, repeated to {} bytes", bytes.len());
+
+    report(
+        "decode",
+        bytes.len(),
+        best(|| {
+            let (mut offset, mut count) = (0, 0);
+            while let Some(decoded) = decode(&bytes, offset) {
+                offset += decoded.length;
+                count += 1;
+            }
+            count
+        }),
+    );
+    report(
+        "disassemble",
+        bytes.len(),
+        best(|| disassemble(&bytes, 0).len()),
+    );
+}
@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, fs, io, ops::Range};
+
+use crate::{
+    data::reached_lines,
+    flow::{Address, ADDRESS_LIMIT},
+    functions::Function,
+    globals::Line,
+    memory::parse_address,
+};
+
+/*
+    Names, comments, code/data regions and function signatures somebody
+    worked out by hand, kept in a file next to the image so every run picks
+    them up again. The file is TOML, just the part of it we need:
+
+        [labels]
+        0x8000 = "main"
+        0xc0de = "uart_puts"
+
+        [comments]
+        0x8004 = "stop the watchdog"
+
+        [code]          # start = end, end not included
+        0x9000 = 0x9100
+
+        [data]
+        0xa000 = 0xa040
+
+        [signatures]
+        0xc0de = "void uart_puts(const char *s)"
+
+    Addresses are CPU addresses, 0x hex or decimal like everywhere else
+    (memory::parse_address), and keys can be quoted. A label on a function start renames the
+    function everywhere, anywhere else it's printed above the line. A code
+    region or a signature makes its start a function even if nothing calls
+    it, and lines in a code region that no function reached go to the one
+    at its start. Those are only claimed, the listing shows them as code
+    but analyses that follow flow never see them. A data region takes lines back out of whatever function
+    had them and is read as data on its own, see data::find_data.
+*/
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Annotations {
+    pub labels: BTreeMap<u32, String>,
+    pub comments: BTreeMap<u32, String>,
+    pub code: BTreeMap<u32, u32>, // start -> end
+    pub data: BTreeMap<u32, u32>,
+    pub signatures: BTreeMap<u32, String>,
+}
+
+// where the annotations for an image live unless --notes says otherwise
+pub fn sidecar(image: &str) -> String {
+    format!("{image}.notes.toml")
+}
+
+// a key is an address something is at, unlike the end of a region it can't be ADDRESS_LIMIT
+fn parse_key(text: &str) -> Option<u32> {
+    parse_address(text.trim().trim_matches('"')).filter(|&address| address < ADDRESS_LIMIT)
+}
+
+// a "basic string", the only escapes worth supporting are quotes, backslashes and newlines
+fn parse_string(text: &str) -> Option<String> {
+    let mut chars = text.trim_start().strip_prefix('"')?.chars();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                c @ ('"' | '\\') => value.push(c),
+                _ => return None,
+            },
+            c => value.push(c),
+        }
+    }
+    // nothing but a comment after it
+    let rest = chars.as_str().trim();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+// argument count and whether it returns anything, from a C prototype
+pub fn signature_shape(signature: &str) -> (usize, bool) {
+    let returns = !signature.trim_start().starts_with("void ");
+    let params = signature
+        .split_once('(')
+        .and_then(|(_, rest)| rest.rsplit_once(')'))
+        .map_or("", |(params, _)| params.trim());
+    let count = match params {
+        "" | "void" => 0,
+        params => params.split(',').count(),
+    };
+    (count, returns)
+}
+
+impl Annotations {
+    pub fn parse(text: &str) -> Result<Annotations, String> {
+        let mut notes = Annotations::default();
+        let mut section = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let error = |problem: &str| format!("line {}: {problem}", n + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .split_once(']')
+                    .ok_or_else(|| error("unclosed ["))?
+                    .0
+                    .trim()
+                    .to_owned();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value"))?;
+            let address = parse_key(key).ok_or_else(|| error("keys are addresses"))?;
+            match section.as_str() {
+                "labels" | "comments" | "signatures" => {
+                    let value = parse_string(value).ok_or_else(|| error("expected a string"))?;
+                    let map = match section.as_str() {
+                        "labels" => &mut notes.labels,
+                        "comments" => &mut notes.comments,
+                        _ => &mut notes.signatures,
+                    };
+                    map.insert(address, value);
+                }
+                "code" | "data" => {
+                    let value = value.split('#').next().unwrap_or("");
+                    let end =
+                        parse_address(value).ok_or_else(|| error("expected an end address"))?;
+                    match section.as_str() {
+                        "code" => notes.code.insert(address, end),
+                        _ => notes.data.insert(address, end),
+                    };
+                }
+                other => return Err(error(&format!("unknown section [{other}]"))),
+            }
+        }
+        Ok(notes)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut text = String::new();
+        let strings = [
+            ("labels", &self.labels),
+            ("comments", &self.comments),
+            ("signatures", &self.signatures),
+        ];
+        for (section, map) in strings {
+            if !map.is_empty() {
+                text += &format!("[{section}]\n");
+                for (address, value) in map {
+                    text += &format!("{address:#06x} = {}\n", quote(value));
+                }
+                text += "\n";
+            }
+        }
+        for (section, map) in [("code", &self.code), ("data", &self.data)] {
+            if !map.is_empty() {
+                text += &format!("[{section}]\n");
+                for (start, end) in map {
+                    text += &format!("{start:#06x} = {end:#06x}\n");
+                }
+                text += "\n";
+            }
+        }
+        text
+    }
+
+    // no file yet is the same as an empty one
+    pub fn load(path: &str) -> Result<Annotations, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Annotations::parse(&text).map_err(|e| format!("{path}: {e}")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Annotations::default()),
+            Err(e) => Err(format!("{path}: {e}")),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+
+    // one change from the command line, like `label 0x8000=main` or `data 0xa000..0xa040`
+    pub fn edit(&mut self, kind: &str, arg: &str) -> Result<(), String> {
+        let pair = |separator: &str| {
+            let (address, value) = arg
+                .split_once(separator)
+                .ok_or_else(|| format!("--{kind} wants address{separator}value"))?;
+            let address =
+                parse_key(address).ok_or_else(|| format!("{address} isn't an address"))?;
+            Ok::<_, String>((address, value.to_owned()))
+        };
+        match kind {
+            "label" | "comment" | "signature" => {
+                let (address, value) = pair("=")?;
+                let map = match kind {
+                    "label" => &mut self.labels,
+                    "comment" => &mut self.comments,
+                    _ => &mut self.signatures,
+                };
+                // an empty value takes the annotation away again
+                match value.is_empty() {
+                    true => map.remove(&address),
+                    false => map.insert(address, value),
+                };
+            }
+            "code" | "data" => {
+                let (start, end) = pair("..")?;
+                let end = parse_address(&end).ok_or_else(|| format!("{end} isn't an address"))?;
+                match kind {
+                    "code" => self.code.insert(start, end),
+                    _ => self.data.insert(start, end),
+                };
+            }
+            _ => return Err(format!("can't annotate {kind}")),
+        }
+        Ok(())
+    }
+
+    pub fn label(&self, line: &Line) -> Option<&String> {
+        self.labels.get(&line.address.0)
+    }
+
+    pub fn comment(&self, line: &Line) -> Option<&String> {
+        self.comments.get(&line.address.0)
+    }
+
+    fn in_data(&self, line: &Line) -> bool {
+        let address = line.address.0;
+        self.data
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, &end)| address < end)
+    }
+
+    pub fn data_regions(&self) -> Vec<Range<u32>> {
+        self.data.iter().map(|(&start, &end)| start..end).collect()
+    }
+
+    // functions that only exist because somebody said so
+    pub fn function_starts(&self) -> Vec<Address> {
+        self.code
+            .keys()
+            .chain(self.signatures.keys())
+            .filter_map(|&address| Address::code(address))
+            .collect()
+    }
+
+    // names and signatures onto the functions, code regions into them, data regions out
+    pub fn apply(&self, lines: &[Line], functions: &mut Vec<Function>) {
+        let data: Vec<bool> = lines.iter().map(|line| self.in_data(line)).collect();
+        let mut owned = reached_lines(functions);
+        for (&start, &end) in &self.code {
+            let Some(function) = functions
+                .iter_mut()
+                .find(|f| lines[f.start].address.0 == start)
+            else {
+                continue;
+            };
+            let claimed: Vec<usize> = (0..lines.len())
+                .filter(|&i| (start..end).contains(&lines[i].address.0))
+                .filter(|&i| !data[i] && !owned.contains(&i))
+                .collect();
+            owned.extend(&claimed);
+            function.claimed.extend(claimed);
+            function.claimed.sort();
+        }
+        functions.retain_mut(|function| {
+            function.body.retain(|&i| !data[i]);
+            match function.body.last() {
+                Some(&last) if !data[function.start] => {
+                    function.end = last.max(function.claimed.last().copied().unwrap_or(0)) + 1
+                }
+                _ => return false,
+            }
+            let start = lines[function.start].address.0;
+            function.label = self.labels.get(&start).cloned();
+            function.signature = self.signatures.get(&start).cloned();
+            true
+        });
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    flow::{block_starts, Address},
+    globals::{Line, PC, SP},
+    ir::{alu, lift, AluOp, Ir, Operand, Width},
+    jumptables::read_word,
+    liveness::CALL_CLOBBERED,
+};
+
+/*
+    Register contents are only tracked inside a basic block, starting from
+    nothing known at every block start. That's enough for the usual
+        MOV #callback, r15 / CALL r15
+        MOV &handlers, r13 / CALL @r13
+    that drivers compile to. Loads from the image are only trusted when they
+    hit the image itself, RAM could hold anything.
+*/
+
+type Registers = [Option<u16>; 16];
+
+// first general purpose register, PC/SP/SR/CG are never tracked
+const FIRST_TRACKED: u8 = 4;
+
+fn get(regs: &Registers, reg: u8) -> Option<u16> {
+    if reg < FIRST_TRACKED {
+        return None;
+    }
+    regs[reg as usize]
+}
+
+fn set(regs: &mut Registers, reg: u8, value: Option<u16>) {
+    if reg >= FIRST_TRACKED {
+        regs[reg as usize] = value;
+    }
+}
+
+fn load(bytes: &[u8], base: u32, address: u16, width: Width) -> Option<u16> {
+    match width {
+        Width::Byte => {
+            let offset = u32::from(address).checked_sub(base)?;
+            bytes.get(offset as usize).map(|&byte| byte.into())
+        }
+        Width::Word => read_word(bytes, base, address.into()),
+    }
+}
+
+// the value an operand reads, if it can be worked out
+fn value(regs: &Registers, operand: Operand, width: Width, bytes: &[u8], base: u32) -> Option<u16> {
+    match operand {
+        Operand::Reg(reg) => get(regs, reg).map(|v| v & width.mask()),
+        Operand::Imm(value) => Some(value & width.mask()),
+        Operand::Absolute(address) => load(bytes, base, address, width),
+        Operand::Indirect(reg) | Operand::IndirectIncrement(reg) => {
+            load(bytes, base, get(regs, reg)?, width)
+        }
+        Operand::Indexed(reg, offset) => {
+            load(bytes, base, get(regs, reg)?.wrapping_add(offset), width)
+        }
+    }
+}
+
+fn post_increment(regs: &mut Registers, operand: Operand, width: Width) {
+    if let Operand::IndirectIncrement(reg) = operand {
+        let step = if reg == SP { 2 } else { width.bytes() };
+        set(regs, reg, get(regs, reg).map(|v| v.wrapping_add(step)));
+    }
+}
+
+// where an indirect CALL, BR or MOV x, PC goes, as a CPU address
+fn branch_target(regs: &Registers, ir: &Ir, bytes: &[u8], base: u32) -> Option<u16> {
+    match *ir {
+        Ir::Call {
+            target: Operand::Imm(_),
+        } => None, // call_target already knows
+        Ir::Call { target } => value(regs, target, Width::Word, bytes, base),
+        Ir::Alu {
+            op: AluOp::Mov,
+            src,
+            dst: Operand::Reg(PC),
+            ..
+        } => value(regs, src, Width::Word, bytes, base),
+        _ => None,
+    }
+}
+
+fn step(regs: &mut Registers, ir: &Ir, bytes: &[u8], base: u32) {
+    match *ir {
+        Ir::Alu {
+            op,
+            width,
+            src,
+            dst,
+        } => {
+            let s = value(regs, src, width, bytes, base);
+            post_increment(regs, src, width);
+            let Operand::Reg(reg) = dst else {
+                return;
+            };
+            if !op.writes_result() {
+                return;
+            }
+            let d = match op.reads_destination() {
+                true => value(regs, dst, width, bytes, base),
+                false => Some(0),
+            };
+            // carry isn't tracked, so anything that needs it is unknown
+            let result = match (s, d, op.reads_carry()) {
+                (Some(s), Some(d), false) => Some(alu(op, width, s, d, false).value),
+                _ => None,
+            };
+            set(regs, reg, result);
+        }
+        Ir::Push { width, src } => post_increment(regs, src, width),
+        Ir::Call { .. } => {
+            for reg in CALL_CLOBBERED {
+                set(regs, reg, None);
+            }
+        }
+        _ => (),
+    }
+}
+
+// fills in targets for indirect calls and branches whose register is known
+pub fn propagate_constants(lines: &mut [Line], bytes: &[u8], base: u32) {
+    let starts = block_starts(lines);
+    let mut regs: Registers = [None; 16];
+    for (i, line) in lines.iter_mut().enumerate() {
+        if starts.contains(&i) {
+            regs = [None; 16];
+        }
+        let ir = lift(line);
+        if line.targets.is_empty() {
+            let target = branch_target(&regs, &ir, bytes, base)
+                .and_then(|target| Address::code(target.into()));
+            if let Some(target) = target {
+                line.targets.push(target);
+            }
+        }
+        step(&mut regs, &ir, bytes, base);
+    }
+}
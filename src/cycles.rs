@@ -0,0 +1,152 @@
+use crate::{
+    flow::{ends_block, jump_target, Address},
+    globals::{AddressMode, Instruction, Line, OneOpcode, TwoOpcode, PC},
+};
+
+/*
+    Cycle counts straight from the family user's guides:
+        MSP430  (CPU)       SLAU144 section 3.4.4
+        MSP430X (CPUXv2)    SLAU208 section 4.6.1
+    These only cover the plain MSP430 instructions we decode, not the
+    20 bit address instructions (MOVA, CALLA, PUSHM, ...).
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cpu {
+    MSP430,
+    MSP430X,
+}
+
+// how the timing tables group addressing modes
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Register,  // Rn and every constant generator value
+    Indirect,  // @Rn
+    Increment, // @Rn+
+    Immediate, // #N, really @PC+
+    Memory,    // x(Rn), EDE and &EDE
+    Absolute,  // &EDE, only differs from Memory for CALL on the MSP430X
+}
+
+fn classify(am: AddressMode, reg: u8) -> Operand {
+    use AddressMode::*;
+    match am {
+        Direct => Operand::Register,
+        Indexed => Operand::Memory,
+        Indirect => Operand::Indirect,
+        IndirectIncrement if reg == PC => Operand::Immediate,
+        IndirectIncrement => Operand::Increment,
+        AbsoluteAddressing => Operand::Absolute,
+        Const4 | Const8 | Const0 | Const1 | Const2 | ConstNeg1 => Operand::Register,
+    }
+}
+
+// takes the instruction as the decoder returns it in raw, check_special_am hides
+// which immediates came from the constant generator and cost nothing extra
+pub fn cycles(instruction: &Instruction, cpu: Cpu) -> Option<u8> {
+    match instruction {
+        Instruction::JMP { .. } => Some(2),
+        Instruction::ONE {
+            opcode, dam, dest, ..
+        } => Some(format_two_cycles(*opcode, classify(*dam, dest.0), cpu)),
+        Instruction::TWO {
+            opcode,
+            src,
+            dam,
+            sam,
+            dest,
+            ..
+        } => Some(format_one_cycles(
+            *opcode,
+            classify(*sam, src.0),
+            classify(*dam, dest.0),
+            dest.0 == PC,
+            cpu,
+        )),
+        _ => None,
+    }
+}
+
+fn format_two_cycles(opcode: OneOpcode, operand: Operand, cpu: Cpu) -> u8 {
+    use Operand::*;
+    match (opcode, cpu) {
+        (OneOpcode::RETI, _) => 5,
+        (OneOpcode::PUSH, Cpu::MSP430) => match operand {
+            Register => 3,
+            Indirect | Immediate => 4,
+            Increment | Memory | Absolute => 5,
+        },
+        (OneOpcode::PUSH, Cpu::MSP430X) => match operand {
+            Register | Indirect | Increment | Immediate => 3,
+            Memory | Absolute => 4,
+        },
+        (OneOpcode::CALL, Cpu::MSP430) => match operand {
+            Register | Indirect => 4,
+            Increment | Immediate | Memory | Absolute => 5,
+        },
+        (OneOpcode::CALL, Cpu::MSP430X) => match operand {
+            Register | Indirect | Increment | Immediate => 4,
+            Memory => 5,
+            Absolute => 6,
+        },
+        // RRA, RRC, SWPB and SXT
+        _ => match operand {
+            Register => 1,
+            Indirect | Increment | Immediate => 3,
+            Memory | Absolute => 4,
+        },
+    }
+}
+
+fn format_one_cycles(
+    opcode: TwoOpcode,
+    source: Operand,
+    destination: Operand,
+    to_pc: bool,
+    cpu: Cpu,
+) -> u8 {
+    use Operand::*;
+    let (to_register, to_pc_cycles, to_memory) = match (source, cpu) {
+        (Register, Cpu::MSP430) => (1, 2, 4),
+        (Indirect, Cpu::MSP430) => (2, 2, 5),
+        (Increment | Immediate, Cpu::MSP430) => (2, 3, 5),
+        (Memory | Absolute, Cpu::MSP430) => (3, 3, 6),
+        (Register, Cpu::MSP430X) => (1, 3, 4),
+        (Indirect | Increment | Immediate, Cpu::MSP430X) => (2, 3, 5),
+        (Memory | Absolute, Cpu::MSP430X) => (3, 4, 6),
+    };
+    match destination {
+        Register if to_pc => to_pc_cycles,
+        Register => to_register,
+        _ => {
+            // MOV never reads the destination and BIT/CMP never write it, the MSP430X saves a cycle there
+            let read_only = matches!(opcode, TwoOpcode::MOV | TwoOpcode::BIT | TwoOpcode::CMP);
+            if cpu == Cpu::MSP430X && read_only {
+                to_memory - 1
+            } else {
+                to_memory
+            }
+        }
+    }
+}
+
+// splits a listing into basic blocks, returns the index of the last line of
+// every block together with its total
+pub fn block_cycles(lines: &[Line], cpu: Cpu) -> Vec<(usize, u32)> {
+    let targets: Vec<Address> = lines.iter().filter_map(jump_target).collect();
+
+    let mut blocks = Vec::new();
+    let mut total = 0;
+    for (i, line) in lines.iter().enumerate() {
+        total += cycles(&line.raw, cpu).unwrap_or(0) as u32;
+
+        let next_is_target = lines
+            .get(i + 1)
+            .is_none_or(|next| targets.contains(&next.address));
+        if ends_block(&line.instruction) || next_is_target {
+            blocks.push((i, total));
+            total = 0;
+        }
+    }
+    blocks
+}
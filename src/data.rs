@@ -0,0 +1,237 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::{
+    flow::Address,
+    functions::Function,
+    globals::{AddressMode, Instruction, Line, PC},
+};
+
+/*
+    Anything no function reaches is data. Unreached stretches of the
+    listing, cut at the edges of any data regions from the annotations so
+    each region starts an item of its own, are re-read byte by byte and
+    split into:
+        NUL terminated ASCII strings
+        padding, runs of erased flash (0xffff)
+        pointer tables, words that are the address of a reachable instruction
+        plain words, and single bytes left over after an odd length string
+*/
+
+const MIN_STRING: usize = 4;
+const WORDS_PER_LINE: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum DataKind {
+    Ascii(Vec<u8>),
+    Byte(u8),
+    Words(Vec<u16>),
+    Pointers(Vec<u16>),
+    Fill(usize), // number of 0xffff words
+}
+
+impl DataKind {
+    pub fn bytes(&self) -> usize {
+        match self {
+            DataKind::Ascii(bytes) => bytes.len(),
+            DataKind::Byte(_) => 1,
+            DataKind::Words(words) | DataKind::Pointers(words) => words.len() * 2,
+            DataKind::Fill(count) => count * 2,
+        }
+    }
+
+    // what --stats calls it
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataKind::Ascii(_) => "strings",
+            DataKind::Byte(_) => "bytes",
+            DataKind::Words(_) => "words",
+            DataKind::Pointers(_) => "pointer tables",
+            DataKind::Fill(_) => "padding",
+        }
+    }
+}
+
+pub struct DataItem {
+    pub address: Address,
+    pub kind: DataKind,
+    pub xrefs: Vec<Address>, // instructions that load this item's address
+}
+
+// the assembler directive for an item, strings escaped the way gas wants them
+pub fn directive(kind: &DataKind) -> String {
+    match kind {
+        DataKind::Ascii(bytes) => {
+            let text: String = bytes.iter().map(|&b| escape(b)).collect();
+            format!(".ascii \"{text}\"")
+        }
+        DataKind::Byte(byte) => format!(".byte  {byte:#04x}"),
+        DataKind::Words(words) | DataKind::Pointers(words) => {
+            let words: Vec<String> = words.iter().map(|w| format!("{w:#06x}")).collect();
+            format!(".word  {}", words.join(", "))
+        }
+        DataKind::Fill(count) => format!(".fill  {count}, 2, 0xffff"),
+    }
+}
+
+fn escape(byte: u8) -> String {
+    match byte {
+        0 => "\\0".to_owned(),
+        b'\n' => "\\n".to_owned(),
+        b'\r' => "\\r".to_owned(),
+        b'\t' => "\\t".to_owned(),
+        b'"' => "\\\"".to_owned(),
+        b'\\' => "\\\\".to_owned(),
+        _ => (byte as char).to_string(),
+    }
+}
+
+fn printable(byte: u8) -> bool {
+    (0x20..0x7f).contains(&byte) || matches!(byte, b'\n' | b'\r' | b'\t')
+}
+
+// length of the string at bytes[at..end] including its NUL, if there is one
+fn string_at(bytes: &[u8], at: usize, end: usize) -> Option<usize> {
+    let text = bytes[at..end].iter().take_while(|&&b| printable(b)).count();
+    let terminated = bytes.get(at + text) == Some(&0) && at + text < end;
+    (text >= MIN_STRING && terminated).then_some(text + 1)
+}
+
+// the line index of every instruction some function reaches, or was given by a code region
+pub fn reached_lines(functions: &[Function]) -> HashSet<usize> {
+    functions
+        .iter()
+        .flat_map(|f| f.body.iter().chain(&f.claimed).copied())
+        .collect()
+}
+
+// bytes[0] is at CPU address base, like analyze was told, regions are CPU addresses
+pub fn find_data(
+    lines: &[Line],
+    functions: &[Function],
+    bytes: &[u8],
+    base: u32,
+    regions: &[Range<u32>],
+) -> Vec<DataItem> {
+    let in_region = |i: usize| regions.iter().any(|r| r.contains(&lines[i].address.0));
+    let reached: HashSet<usize> = reached_lines(functions)
+        .into_iter()
+        .filter(|&i| !in_region(i))
+        .collect();
+    let code: HashSet<u32> = reached.iter().map(|&i| lines[i].address.0).collect();
+    let edges: HashSet<u32> = regions.iter().flat_map(|r| [r.start, r.end]).collect();
+    let offset = |line: &Line| (line.address.0 - base) as usize;
+
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if reached.contains(&i) {
+            i += 1;
+            continue;
+        }
+        let start = offset(&lines[i]);
+        i += 1;
+        while i < lines.len() && !reached.contains(&i) && !edges.contains(&lines[i].address.0) {
+            i += 1;
+        }
+        let end = match lines.get(i) {
+            Some(line) => offset(line),
+            None => bytes.len(),
+        };
+        classify(bytes, base, start, end, &code, &mut items);
+    }
+
+    // MOV #addr, rN and friends pointing at the start of an item
+    let by_address: HashMap<u32, usize> = items
+        .iter()
+        .enumerate()
+        .map(|(n, item)| (item.address.0, n))
+        .collect();
+    for &i in &reached {
+        let Some(target) = immediate_address(&lines[i].instruction) else {
+            continue;
+        };
+        if let Some(&n) = by_address.get(&target) {
+            items[n].xrefs.push(lines[i].address);
+        }
+    }
+    for item in items.iter_mut() {
+        item.xrefs.sort();
+    }
+    items
+}
+
+// the CPU address an immediate source operand would point at if it were an address
+pub fn immediate_address(instruction: &Instruction) -> Option<u32> {
+    match *instruction {
+        Instruction::TWO {
+            src,
+            sam: AddressMode::IndirectIncrement,
+            src_index: Some(word),
+            ..
+        } if src.0 == PC => Some(word.0.into()),
+        _ => None,
+    }
+}
+
+// start and end are offsets into bytes
+fn classify(
+    bytes: &[u8],
+    base: u32,
+    start: usize,
+    end: usize,
+    code: &HashSet<u32>,
+    items: &mut Vec<DataItem>,
+) {
+    let word_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let is_pointer =
+        |word: u16| Address::code(word.into()).is_some_and(|address| code.contains(&address.0));
+    let mut push = |at: usize, kind: DataKind| {
+        items.push(DataItem {
+            address: Address(base + at as u32),
+            kind,
+            xrefs: Vec::new(),
+        })
+    };
+
+    let mut at = start;
+    while at < end {
+        if let Some(len) = string_at(bytes, at, end) {
+            push(at, DataKind::Ascii(bytes[at..at + len].to_vec()));
+            at += len;
+            continue;
+        }
+        if at % 2 == 1 || at + 1 >= end {
+            push(at, DataKind::Byte(bytes[at]));
+            at += 1;
+            continue;
+        }
+
+        let first = word_at(at);
+        let mut words = vec![first];
+        let same_kind = |word: u16| match first {
+            0xffff => word == 0xffff,
+            _ if is_pointer(first) => is_pointer(word),
+            _ => word != 0xffff && !is_pointer(word),
+        };
+        let mut next = at + 2;
+        while next + 1 < end
+            && same_kind(word_at(next))
+            && string_at(bytes, next, end).is_none()
+            && (first == 0xffff || words.len() < WORDS_PER_LINE)
+        {
+            words.push(word_at(next));
+            next += 2;
+        }
+
+        let kind = match first {
+            0xffff => DataKind::Fill(words.len()),
+            _ if is_pointer(first) => DataKind::Pointers(words),
+            _ => DataKind::Words(words),
+        };
+        push(at, kind);
+        at = next;
+    }
+}
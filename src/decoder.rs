@@ -0,0 +1,216 @@
+use crate::{flow::Address, globals::*, pseudo::check_pseudo};
+
+/*
+    Decoding is a plain function of the bytes and where the instruction
+    starts, nothing is kept between calls. That means any offset can be
+    decoded on its own (the emulator decodes straight out of its memory),
+    a listing can look backwards for what ends where it starts, and as
+    many cursors as you like can walk the same slice at once.
+
+    Bytes are the image as it is in the file, little endian words. An
+    instruction that runs off the end reads zeros for its missing
+    extension words, like the flash past the end of a truncated dump.
+*/
+
+pub struct Decoded {
+    pub raw: Instruction,         // exactly what the bits say
+    pub instruction: Instruction, // constant generators and emulated instructions worked out
+    pub length: usize,            // in bytes, 2, 4 or 6
+}
+
+fn word_at(bytes: &[u8], offset: usize) -> Word {
+    match bytes.get(offset..offset + 2) {
+        Some(pair) => Word(u16::from_le_bytes([pair[0], pair[1]])),
+        None => Word(0),
+    }
+}
+
+// the instruction at offset, None if one can't start there
+pub fn decode(bytes: &[u8], offset: usize) -> Option<Decoded> {
+    if offset % 2 == 1 || offset + 2 > bytes.len() {
+        return None;
+    }
+    let word = word_at(bytes, offset);
+    let mut next = offset + 2;
+    let mut extension = || {
+        let word = word_at(bytes, next);
+        next += 2;
+        word
+    };
+    let raw = match flavor(word) {
+        InstructionFlavor::ONE => one_operand(word, &mut extension),
+        InstructionFlavor::TWO => two_operand(word, &mut extension),
+        InstructionFlavor::JMP => jump(word),
+    };
+    let mut instruction = check_special_am(&raw);
+    if let Some(pseudo) = check_pseudo(instruction) {
+        instruction = pseudo
+    }
+    Some(Decoded {
+        raw,
+        instruction,
+        length: next - offset,
+    })
+}
+
+// a listing line for the instruction at offset, with bytes[0] at CPU address base
+pub fn line_at(bytes: &[u8], base: u32, offset: usize) -> Option<Line> {
+    let decoded = decode(bytes, offset)?;
+    let words = (0..decoded.length)
+        .step_by(2)
+        .map(|n| word_at(bytes, offset + n))
+        .collect();
+    Some(Line {
+        address: Address(base + offset as u32),
+        words: UsedWords(words),
+        raw: decoded.raw,
+        instruction: decoded.instruction,
+        targets: Vec::new(),
+    })
+}
+
+// where an instruction ending right at offset could start, nearest first.
+// usually one answer, sometimes more, the encoding doesn't say
+pub fn starts_before(bytes: &[u8], offset: usize) -> Vec<usize> {
+    [2, 4, 6]
+        .into_iter()
+        .filter_map(|length| offset.checked_sub(length))
+        .filter(|&start| decode(bytes, start).is_some_and(|d| start + d.length == offset))
+        .collect()
+}
+
+// walks a slice one instruction at a time, copy it to branch off
+#[derive(Clone, Copy)]
+pub struct Cursor<'b> {
+    pub bytes: &'b [u8],
+    pub base: u32,     // CPU address of bytes[0]
+    pub offset: usize, // the next instruction
+    pub last: Address, // the one decoded before it
+}
+
+impl<'b> Cursor<'b> {
+    pub fn new(bytes: &'b [u8], base: u32) -> Cursor<'b> {
+        Cursor {
+            bytes,
+            base,
+            offset: 0,
+            last: Address(base),
+        }
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        let line = line_at(self.bytes, self.base, self.offset)?;
+        self.last = line.address;
+        self.offset += line.words.0.len() * 2;
+        Some(line)
+    }
+}
+
+// these leave the cursor on the target, jumps are relative to the instruction it decoded last
+impl Cursor<'_> {
+    pub fn jump_to_offset(&mut self, offset: Offset) {
+        self.jump_to(self.last + offset.0);
+    }
+    pub fn jump_to_absolute(&mut self, address: u32) {
+        if let Some(address) = Address::code(address) {
+            self.jump_to(address);
+        }
+    }
+    fn jump_to(&mut self, address: Address) {
+        match address.0.checked_sub(self.base) {
+            Some(offset) if (offset as usize) < self.bytes.len() => self.offset = offset as usize,
+            _ => (),
+        }
+    }
+}
+
+fn flavor(word: Word) -> InstructionFlavor {
+    match word.0 >> 10 {
+        0b000100 => InstructionFlavor::ONE,
+        0b001000..=0b001111 => InstructionFlavor::JMP,
+        _ => InstructionFlavor::TWO,
+    }
+}
+
+// bits `at` and up, `width` of them
+fn field(word: Word, at: u32, width: u32) -> usize {
+    ((word.0 >> at) & ((1 << width) - 1)) as usize
+}
+
+// the addressing mode table a register uses, r2 and r3 double as constant generators
+fn address_mode(reg: u8, bits: usize) -> AddressMode {
+    match reg {
+        SR => ADDRESS_MODES_SR[bits],
+        ZR => ADDRESS_MODES_ZERO[bits],
+        _ => ADDRESS_MODES[bits],
+    }
+}
+
+// the extension word an operand takes, if it takes one
+fn operand_index(mode: AddressMode, reg: u8, extension: &mut impl FnMut() -> Word) -> Option<Word> {
+    match mode {
+        AddressMode::IndirectIncrement if reg == PC => Some(extension()), // #immediate
+        AddressMode::Indexed | AddressMode::AbsoluteAddressing => Some(extension()),
+        _ => None,
+    }
+}
+
+fn one_operand(word: Word, extension: &mut impl FnMut() -> Word) -> Instruction {
+    let opcode = match ONE_OPERAND[field(word, 7, 3)] {
+        Some(opcode) => opcode,
+        None => return Instruction::Invalid, // 0b111 is unused on the plain MSP430
+    };
+    let dest = DestReg(field(word, 0, 4) as u8);
+    let dam = address_mode(dest.0, field(word, 4, 2));
+    let dest_index = match opcode {
+        OneOpcode::RETI => None, // RETI ignores its operand bits
+        _ => operand_index(dam, dest.0, extension),
+    };
+    Instruction::ONE {
+        opcode,
+        b: Bbit(field(word, 6, 1) == 1),
+        dam,
+        dest,
+        dest_index,
+    }
+}
+
+fn two_operand(word: Word, extension: &mut impl FnMut() -> Word) -> Instruction {
+    let opcode = match TWO_OPERAND[field(word, 12, 4)] {
+        Some(opcode) => opcode,
+        None => return Instruction::Invalid, // MSP430X extension words, not handled yet
+    };
+    let src = SrcReg(field(word, 8, 4) as u8);
+    let dest = DestReg(field(word, 0, 4) as u8);
+    let sam = address_mode(src.0, field(word, 4, 2));
+    // the constant generator only works for sources, x(r3) as a destination is still indexed
+    let dam = match dest.0 {
+        SR => ADDRESS_MODES_SR[field(word, 7, 1)],
+        _ => ADDRESS_MODES[field(word, 7, 1)],
+    };
+    // source first, that's the order the extension words come in
+    let src_index = operand_index(sam, src.0, extension);
+    let dest_index = operand_index(dam, dest.0, extension);
+    Instruction::TWO {
+        opcode,
+        src,
+        dam,
+        b: Bbit(field(word, 6, 1) == 1),
+        sam,
+        dest,
+        src_index,
+        dest_index,
+    }
+}
+
+fn jump(word: Word) -> Instruction {
+    let condition = JUMPS[field(word, 10, 3)];
+    // shifting the 10 bit offset to the top and back sign extends it. Still, thanks for Retr0id and Stuckpixel for helping me out with learning how to do it
+    // These are all PC-relative jumps, adding twice the sign-extended offset to the PC, for a jump range of -1024 to +1022 (http://mspgcc.sourceforge.net/manual/x223.html)
+    let offset = Offset(((word.0 << 6) as i16 >> 6) * 2 + 2);
+    Instruction::JMP { condition, offset }
+}
@@ -0,0 +1,742 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    annotations::signature_shape,
+    device::Register,
+    flow::{
+        block_starts, call_target, ends_block, index_by_address, is_conditional, jump_target,
+        Address,
+    },
+    functions::{is_return, Function, FunctionKind},
+    globals::{Instruction, JmpOpcode, Line, PC, SP, SR, ZR},
+    ir::{lift, AluOp, Flags, Ir, Operand, Width, C, GIE, N, Z},
+    liveness::{def_use, live_registers, liveness, RegSet},
+};
+
+/*
+    Rough C for reading, not for compiling. Every register is a uint16_t
+    variable, the flags are `carry`, `zero` and `negative` when a branch
+    can't be tied back to the CMP/TST/BIT that set them.
+
+    Structure comes from the block layout msp430-gcc produces:
+        a conditional jump forwards over some blocks is an if, and if the
+        skipped blocks end in a JMP further forwards it's an if/else
+        a jump backwards to a block is a loop around everything in between
+    Anything that doesn't fit that shape is a goto.
+*/
+
+const INDENT: &str = "    ";
+
+// what a caller needs to know about a function
+struct Signature {
+    name: String,
+    params: usize, // arguments in r12, r13, ...
+    returns: bool, // leaves something in r12
+}
+
+struct Block {
+    first: usize, // line indices, inclusive
+    last: usize,
+}
+
+struct Loop {
+    header: usize, // block indices
+    exit: usize,
+}
+
+struct Decompiler<'a> {
+    lines: &'a [Line],
+    by_address: &'a HashMap<Address, usize>,
+    live: &'a [RegSet],
+    signatures: &'a HashMap<Address, Signature>,
+    registers: &'a [Register],
+    returns: bool,
+    blocks: Vec<Block>,
+    block_of: HashMap<usize, usize>, // first line of a block -> block
+    loops: Vec<Loop>,
+    handled: HashSet<usize>, // blocks whose closing jump the structure already says
+    out: Vec<(usize, String)>,
+    depth: usize,
+    gotos: HashSet<usize>,
+    positions: HashMap<usize, usize>, // block -> where its first statement went in out
+}
+
+fn reg_name(reg: u8) -> String {
+    match reg {
+        PC => "pc".to_owned(),
+        SP => "sp".to_owned(),
+        SR => "sr".to_owned(),
+        _ => format!("r{reg}"),
+    }
+}
+
+// highest of r12-r15 in the set decides how many arguments there are
+fn argument_count(regs: RegSet) -> usize {
+    (12..=15)
+        .rev()
+        .find(|&r| regs.contains(r))
+        .map_or(0, |r| r as usize - 11)
+}
+
+fn arguments(count: usize) -> String {
+    let args: Vec<String> = (12..12 + count as u8).map(reg_name).collect();
+    args.join(", ")
+}
+
+fn cast(width: Width) -> &'static str {
+    match width {
+        Width::Byte => "uint8_t",
+        Width::Word => "uint16_t",
+    }
+}
+
+// peripheral registers go by their name in the device's table
+fn operand(operand: Operand, width: Width, registers: &[Register]) -> String {
+    let t = cast(width);
+    match operand {
+        Operand::Reg(reg) => reg_name(reg),
+        Operand::Imm(value) => format!("{value:#x}"),
+        Operand::Indirect(reg) | Operand::IndirectIncrement(reg) => {
+            format!("*({t} *){}", reg_name(reg))
+        }
+        Operand::Indexed(reg, offset) => match offset as i16 {
+            0 => format!("*({t} *){}", reg_name(reg)),
+            offset if offset < 0 => {
+                format!("*({t} *)({} - {:#x})", reg_name(reg), -(offset as i32))
+            }
+            offset => format!("*({t} *)({} + {offset:#x})", reg_name(reg)),
+        },
+        Operand::Absolute(address) => match registers.iter().find(|r| r.address == address) {
+            Some(register) => register.name.to_owned(),
+            None => format!("*({t} *){address:#06x}"),
+        },
+    }
+}
+
+// @rN+ as a separate statement after the one that reads it
+fn post_increment(operand: Operand, width: Width) -> Option<String> {
+    match operand {
+        Operand::IndirectIncrement(SP) => Some("sp += 2;".to_owned()),
+        Operand::IndirectIncrement(reg) => Some(format!("{} += {};", reg_name(reg), width.bytes())),
+        _ => None,
+    }
+}
+
+// BIC/BIS #bit, SR for the bits C code has a name for
+fn status_bit(set: bool, bit: u16) -> Option<String> {
+    let flag = match bit {
+        C => "carry",
+        Z => "zero",
+        N => "negative",
+        GIE if set => return Some("__enable_interrupt();".to_owned()),
+        GIE => return Some("__disable_interrupt();".to_owned()),
+        _ => return None,
+    };
+    Some(format!("{flag} = {};", set as u8))
+}
+
+// one ALU operation, the emulated instructions (INC, CLR, RLA...) get their usual C
+fn alu_statement(
+    op: AluOp,
+    width: Width,
+    src: Operand,
+    dst: Operand,
+    registers: &[Register],
+) -> Option<String> {
+    use AluOp::*;
+    use Operand::{Imm, Reg};
+    if let (Bic | Bis, Imm(bit), Reg(SR)) = (op, src, dst) {
+        if let Some(text) = status_bit(op == Bis, bit) {
+            return Some(text);
+        }
+    }
+    let s = operand(src, width, registers);
+    let d = operand(dst, width, registers);
+    Some(match (op, src) {
+        (Mov, Imm(0)) => format!("{d} = 0;"),
+        (Mov, _) if width == Width::Byte && matches!(dst, Reg(_)) => {
+            format!("{d} = (uint8_t){s};")
+        }
+        (Mov, _) => format!("{d} = {s};"),
+        (Add, Imm(1)) => format!("{d}++;"),
+        (Add, Imm(2)) => format!("{d} += 2;"),
+        (Add, _) if src == dst => format!("{d} <<= 1;"),
+        (Add, _) => format!("{d} += {s};"),
+        (Addc, Imm(0)) => format!("{d} += carry;"),
+        (Addc, _) if src == dst => format!("{d} = {d} << 1 | carry;"),
+        (Addc, _) => format!("{d} += {s} + carry;"),
+        (Sub, Imm(1)) => format!("{d}--;"),
+        (Sub, Imm(2)) => format!("{d} -= 2;"),
+        (Sub, _) => format!("{d} -= {s};"),
+        (Subc, Imm(0)) => format!("{d} -= !carry;"),
+        (Subc, _) => format!("{d} -= {s} + !carry;"),
+        (Dadd, Imm(0)) => format!("{d} = __bcd_add({d}, carry);"),
+        (Dadd, _) => format!("{d} = __bcd_add({d}, {s});"),
+        (And, _) => format!("{d} &= {s};"),
+        (Bic, _) => format!("{d} &= ~{s};"),
+        (Bis, _) => format!("{d} |= {s};"),
+        (Xor, Imm(0xffff)) => format!("{d} = ~{d};"),
+        (Xor, _) => format!("{d} ^= {s};"),
+        (Rrc, _) => format!("{d} = {d} >> 1 | carry << 15;"),
+        (Rra, _) => format!("{d} = (int16_t){d} >> 1;"),
+        (Swpb, _) => format!("{d} = __swap_bytes({d});"),
+        (Sxt, _) => format!("{d} = (int8_t){d};"),
+        (Cmp | Bit, _) => return None, // only there for the branch after it
+    })
+}
+
+fn flag_name(condition: JmpOpcode, negate: bool) -> String {
+    let (flag, set) = match condition {
+        JmpOpcode::JNE => ("zero", false),
+        JmpOpcode::JEQ => ("zero", true),
+        JmpOpcode::JLO => ("carry", false),
+        JmpOpcode::JHS => ("carry", true),
+        JmpOpcode::JN => ("negative", true),
+        JmpOpcode::JGE => ("negative == overflow", true),
+        JmpOpcode::JL => ("negative == overflow", false),
+        JmpOpcode::JMP => return "1".to_owned(),
+    };
+    match set != negate {
+        true => flag.to_owned(),
+        false if flag.contains(' ') => format!("!({flag})"),
+        false => format!("!{flag}"),
+    }
+}
+
+// `dst op src` for a compare, flipped if the branch is being negated
+fn comparison(condition: JmpOpcode, negate: bool, d: &str, s: &str) -> String {
+    use JmpOpcode::*;
+    let condition = match (condition, negate) {
+        (c, false) => c,
+        (JEQ, true) => JNE,
+        (JNE, true) => JEQ,
+        (JHS, true) => JLO,
+        (JLO, true) => JHS,
+        (JGE, true) => JL,
+        (JL, true) => JGE,
+        (JN, true) => return format!("(int16_t)({d} - {s}) >= 0"),
+        (JMP, true) => JMP,
+    };
+    match condition {
+        JEQ => format!("{d} == {s}"),
+        JNE => format!("{d} != {s}"),
+        JHS => format!("{d} >= {s}"),
+        JLO => format!("{d} < {s}"),
+        JGE => format!("(int16_t){d} >= (int16_t){s}"),
+        JL => format!("(int16_t){d} < (int16_t){s}"),
+        JN => format!("(int16_t)({d} - {s}) < 0"),
+        JMP => "1".to_owned(),
+    }
+}
+
+impl Decompiler<'_> {
+    fn emit(&mut self, text: String) {
+        self.out.push((self.depth, text));
+    }
+
+    fn label(&self, block: usize) -> String {
+        let address = self.lines[self.blocks[block].first].address.0;
+        format!("label_{address:04x}")
+    }
+
+    fn goto(&mut self, block: usize) -> String {
+        self.gotos.insert(block);
+        format!("goto {};", self.label(block))
+    }
+
+    fn tail_call(&self, target: Address) -> String {
+        match self.signatures.get(&target) {
+            Some(callee) if callee.returns => {
+                format!("return {}({});", callee.name, arguments(callee.params))
+            }
+            Some(callee) => format!("{}({}); return;", callee.name, arguments(callee.params)),
+            None => format!("goto *{:#06x};", target.0),
+        }
+    }
+
+    // where a jump goes: another block of this function, or somewhere else entirely
+    fn target_block(&self, target: Address) -> Option<usize> {
+        let i = self.by_address.get(&target)?;
+        self.block_of.get(i).copied()
+    }
+
+    // the condition a conditional jump at lines[i] branches on
+    fn condition(&self, i: usize, negate: bool) -> String {
+        let Instruction::JMP { condition, .. } = self.lines[i].instruction else {
+            return "1".to_owned();
+        };
+        let block = &self.blocks[self.block_of_line(i)];
+        let setter = (block.first..i)
+            .rev()
+            .find(|&j| def_use(&self.lines[j]).flags_written != Flags::default());
+        let Some(setter) = setter else {
+            return flag_name(condition, negate);
+        };
+
+        match lift(&self.lines[setter]) {
+            Ir::Alu {
+                op: AluOp::Cmp,
+                width,
+                src,
+                dst,
+            } => {
+                let s = match src {
+                    Operand::Imm(0) => "0".to_owned(), // TST
+                    _ => operand(src, width, self.registers),
+                };
+                comparison(condition, negate, &operand(dst, width, self.registers), &s)
+            }
+            Ir::Alu {
+                op: AluOp::Bit,
+                width,
+                src,
+                dst,
+            } => {
+                let s = operand(src, width, self.registers);
+                let d = operand(dst, width, self.registers);
+                // C is the inverse of Z after BIT
+                let nonzero = matches!(condition, JmpOpcode::JNE | JmpOpcode::JHS) != negate;
+                format!("({d} & {s}) {} 0", if nonzero { "!=" } else { "==" })
+            }
+            _ => {
+                // anything else sets Z and N from its result
+                let written = def_use(&self.lines[setter]).writes;
+                let result = (4..16).find(|&r| written.contains(r));
+                match (result, condition) {
+                    (Some(r), JmpOpcode::JEQ | JmpOpcode::JNE | JmpOpcode::JN) => {
+                        comparison(condition, negate, &reg_name(r), "0")
+                    }
+                    _ => flag_name(condition, negate),
+                }
+            }
+        }
+    }
+
+    fn block_of_line(&self, i: usize) -> usize {
+        self.blocks.partition_point(|b| b.first <= i) - 1
+    }
+
+    fn call(&self, i: usize) -> String {
+        let line = &self.lines[i];
+        let Ir::Call { target } = lift(line) else {
+            return String::new();
+        };
+        let next_reads_r12 = self.live.get(i + 1).is_some_and(|live| live.contains(12))
+            && !self
+                .lines
+                .get(i + 1)
+                .is_some_and(|l| is_return(&l.instruction));
+
+        let (callee, params, returns) =
+            match call_target(line).and_then(|t| self.signatures.get(&t)) {
+                Some(signature) => (signature.name.clone(), signature.params, signature.returns),
+                None => {
+                    // no signature to go by, arguments are whatever was set up right before
+                    let block = &self.blocks[self.block_of_line(i)];
+                    let written = (block.first..i)
+                        .rev()
+                        .take_while(|&j| !matches!(lift(&self.lines[j]), Ir::Call { .. }))
+                        .fold(RegSet::default(), |set, j| {
+                            set.union(def_use(&self.lines[j]).writes)
+                        });
+                    let target = match call_target(line) {
+                        Some(target) => format!("{:#06x}", target.0),
+                        None => operand(target, Width::Word, self.registers),
+                    };
+                    (
+                        format!("(*(void (*)()){target})"),
+                        argument_count(written),
+                        next_reads_r12,
+                    )
+                }
+            };
+        let call = format!("{callee}({});", arguments(params));
+        match returns && next_reads_r12 {
+            true => format!("r12 = {call}"),
+            false => call,
+        }
+    }
+
+    // straight line code, the jump that ends a block is left to the structure
+    fn statements(&mut self, i: usize) {
+        let line = &self.lines[i];
+        let mut post = None;
+        let text = match lift(line) {
+            Ir::Invalid => Some(format!("/* invalid {} */", line.words)),
+            Ir::Jump { .. } => None,
+            Ir::Reti => Some("return;".to_owned()),
+            Ir::Call { target } => {
+                post = post_increment(target, Width::Word);
+                Some(self.call(i))
+            }
+            Ir::Push { width, src } => {
+                post = post_increment(src, width);
+                match src {
+                    // saving callee saved registers is just noise here
+                    Operand::Reg(4..=10) => None,
+                    _ => Some(format!("push({});", operand(src, width, self.registers))),
+                }
+            }
+            // RET
+            Ir::Alu {
+                op: AluOp::Mov,
+                src: Operand::IndirectIncrement(SP),
+                dst: Operand::Reg(PC),
+                ..
+            } => match self.returns {
+                true => Some("return r12;".to_owned()),
+                false => Some("return;".to_owned()),
+            },
+            // BR and anything else that writes PC is left to the terminator
+            Ir::Alu {
+                dst: Operand::Reg(PC),
+                ..
+            } => None,
+            Ir::Alu {
+                op: AluOp::Mov,
+                width,
+                src: Operand::IndirectIncrement(SP),
+                dst,
+            } => match dst {
+                Operand::Reg(4..=10) => None,
+                _ => Some(format!("{} = pop();", operand(dst, width, self.registers))),
+            },
+            // NOP, whether it's MOV #0, r3 or MOV rN, rN
+            Ir::Alu {
+                op: AluOp::Mov,
+                src,
+                dst: dst @ Operand::Reg(reg),
+                ..
+            } if reg == ZR || src == dst => None,
+            Ir::Alu {
+                op,
+                width,
+                src,
+                dst,
+            } => {
+                post = post_increment(src, width);
+                alu_statement(op, width, src, dst, self.registers)
+            }
+        };
+        if let Some(text) = text {
+            self.emit(text);
+        }
+        if let Some(post) = post {
+            self.emit(post);
+        }
+    }
+
+    // the jump or branch that ends a block, when the structure didn't already cover it
+    fn terminator(&mut self, block: usize) {
+        let i = self.blocks[block].last;
+        let line = &self.lines[i];
+        let instruction = line.instruction;
+
+        if let Some(target) = jump_target(line) {
+            let text = match self.target_block(target) {
+                Some(t) if self.loops.last().is_some_and(|l| l.header == t) => {
+                    "continue;".to_owned()
+                }
+                Some(t) if self.loops.last().is_some_and(|l| l.exit == t) => "break;".to_owned(),
+                Some(t) if t == block + 1 => return,
+                Some(t) => self.goto(t),
+                None => self.tail_call(target),
+            };
+            match is_conditional(&instruction) {
+                true => {
+                    let condition = self.condition(i, false);
+                    self.emit(format!("if ({condition}) {text}"));
+                }
+                false => self.emit(text),
+            }
+            return;
+        }
+
+        // BR and MOV x, PC
+        if !ends_block(&instruction)
+            || is_return(&instruction)
+            || matches!(instruction, Instruction::Invalid)
+        {
+            return;
+        }
+        let targets = line.targets.clone();
+        match targets.len() {
+            0 => {
+                let target = match lift(line) {
+                    Ir::Alu { width, src, .. } => operand(src, width, self.registers),
+                    _ => "?".to_owned(),
+                };
+                self.emit(format!("goto *{target};"));
+            }
+            1 => {
+                let text = match self.target_block(targets[0]) {
+                    Some(t) => self.goto(t),
+                    None => self.tail_call(targets[0]),
+                };
+                self.emit(text);
+            }
+            _ => {
+                // the register the table is indexed by
+                let index = match lift(line) {
+                    Ir::Alu {
+                        src:
+                            Operand::Reg(reg)
+                            | Operand::Indexed(reg, _)
+                            | Operand::Indirect(reg)
+                            | Operand::IndirectIncrement(reg),
+                        ..
+                    } => reg_name(reg),
+                    _ => "index".to_owned(),
+                };
+                self.emit(format!("switch ({index}) {{"));
+                for (case, target) in targets.iter().enumerate() {
+                    let text = match self.target_block(*target) {
+                        Some(t) => self.goto(t),
+                        None => self.tail_call(*target),
+                    };
+                    self.emit(format!("case {case}: {text}"));
+                }
+                self.emit("}".to_owned());
+            }
+        }
+    }
+
+    fn jumps_to(&self, block: usize) -> Option<usize> {
+        jump_target(&self.lines[self.blocks[block].last]).and_then(|t| self.target_block(t))
+    }
+
+    fn is_jump(&self, block: usize, conditional: bool) -> bool {
+        let instruction = &self.lines[self.blocks[block].last].instruction;
+        matches!(instruction, Instruction::JMP { .. }) && is_conditional(instruction) == conditional
+    }
+
+    // blocks from..to, to is one past the last
+    fn emit_range(&mut self, from: usize, to: usize, loop_at: Option<usize>) {
+        let mut b = from;
+        while b < to {
+            // a later block in range jumping back here makes this a loop header
+            let back = (b..to).rev().find(|&j| self.jumps_to(j) == Some(b));
+            if let (Some(bottom), true) = (back, loop_at != Some(b)) {
+                self.positions.entry(b).or_insert(self.out.len());
+                let conditional = self.is_jump(bottom, true);
+                self.emit(if conditional { "do {" } else { "while (1) {" }.to_owned());
+                self.loops.push(Loop {
+                    header: b,
+                    exit: bottom + 1,
+                });
+                self.handled.insert(bottom);
+                self.depth += 1;
+                self.emit_range(b, bottom + 1, Some(b));
+                self.depth -= 1;
+                self.loops.pop();
+                match conditional {
+                    true => {
+                        let condition = self.condition(self.blocks[bottom].last, false);
+                        self.emit(format!("}} while ({condition});"));
+                    }
+                    false => self.emit("}".to_owned()),
+                }
+                b = bottom + 1;
+                continue;
+            }
+
+            self.positions.entry(b).or_insert(self.out.len());
+            for i in self.blocks[b].first..=self.blocks[b].last {
+                self.statements(i);
+            }
+            if self.handled.contains(&b) {
+                b += 1;
+                continue;
+            }
+
+            // forward conditional jump over blocks b+1..t, an if or if/else
+            let target = self.jumps_to(b);
+            let in_loop = |t: usize| {
+                self.loops
+                    .last()
+                    .is_some_and(|l| l.header == t || l.exit == t)
+            };
+            if let Some(t) =
+                target.filter(|&t| self.is_jump(b, true) && t > b + 1 && t <= to && !in_loop(t))
+            {
+                let condition = self.condition(self.blocks[b].last, true);
+                let last = t - 1;
+                let after = self
+                    .jumps_to(last)
+                    .filter(|&e| self.is_jump(last, false) && e > t && e <= to && !in_loop(e));
+                self.emit(format!("if ({condition}) {{"));
+                self.depth += 1;
+                if after.is_some() {
+                    self.handled.insert(last);
+                }
+                self.emit_range(b + 1, t, None);
+                self.depth -= 1;
+                match after {
+                    Some(e) => {
+                        self.emit("} else {".to_owned());
+                        self.depth += 1;
+                        self.emit_range(t, e, None);
+                        self.depth -= 1;
+                        self.emit("}".to_owned());
+                        b = e;
+                    }
+                    None => {
+                        self.emit("}".to_owned());
+                        b = t;
+                    }
+                }
+                continue;
+            }
+
+            self.terminator(b);
+            // falling off the end of the function into the next one
+            let last = self.blocks[b].last;
+            let falls = !ends_block(&self.lines[last].instruction)
+                || is_conditional(&self.lines[last].instruction);
+            let next_is_ours = self
+                .blocks
+                .get(b + 1)
+                .is_some_and(|next| next.first == last + 1);
+            if falls && !next_is_ours {
+                let text = match self.lines.get(last + 1) {
+                    Some(next) => self.tail_call(next.address),
+                    None => "/* runs off the end of the image */".to_owned(),
+                };
+                self.emit(text);
+            }
+            b += 1;
+        }
+    }
+}
+
+fn split_blocks(function: &Function, starts: &HashSet<usize>) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for &i in &function.body {
+        match blocks.last_mut() {
+            Some(block) if block.last + 1 == i && !starts.contains(&i) => block.last = i,
+            _ => blocks.push(Block { first: i, last: i }),
+        }
+    }
+    blocks
+}
+
+fn signature(lines: &[Line], live: &[RegSet], function: &Function) -> Signature {
+    // somebody already worked it out
+    if let Some(prototype) = &function.signature {
+        let (params, returns) = signature_shape(prototype);
+        return Signature {
+            name: function.name(lines),
+            params: params.min(4),
+            returns,
+        };
+    }
+    let params = argument_count(live[function.start]);
+    let returns = !matches!(function.kind, FunctionKind::Vector(_))
+        && function.body.iter().any(|&i| {
+            !matches!(lift(&lines[i]), Ir::Call { .. }) && def_use(&lines[i]).writes.contains(12)
+        });
+    Signature {
+        name: function.name(lines),
+        params,
+        returns,
+    }
+}
+
+pub fn decompile(lines: &[Line], functions: &[Function], registers: &[Register]) -> String {
+    let live = liveness(lines);
+    // arguments are what gets read before it's written, not what RET might hand back
+    let read_first = live_registers(lines, RegSet::default());
+    let starts = block_starts(lines);
+    let by_address = index_by_address(lines);
+    let signatures: HashMap<Address, Signature> = functions
+        .iter()
+        .map(|f| (lines[f.start].address, signature(lines, &read_first, f)))
+        .collect();
+
+    let mut text = String::new();
+    for function in functions {
+        let own = &signatures[&lines[function.start].address];
+        let blocks = split_blocks(function, &starts);
+        let block_of = blocks
+            .iter()
+            .enumerate()
+            .map(|(n, b)| (b.first, n))
+            .collect();
+        let mut decompiler = Decompiler {
+            lines,
+            by_address: &by_address,
+            live: &live,
+            signatures: &signatures,
+            registers,
+            returns: own.returns,
+            blocks,
+            block_of,
+            loops: Vec::new(),
+            handled: HashSet::new(),
+            out: Vec::new(),
+            depth: 1,
+            gotos: HashSet::new(),
+            positions: HashMap::new(),
+        };
+        if decompiler.blocks[0].first != function.start {
+            let entry = decompiler.block_of[&function.start];
+            let text = decompiler.goto(entry);
+            decompiler.emit(text);
+        }
+        let count = decompiler.blocks.len();
+        decompiler.emit_range(0, count, None);
+
+        // labels go in last, now that every goto is known
+        let mut labels: Vec<(usize, usize)> = decompiler
+            .gotos
+            .iter()
+            .map(|&b| (decompiler.positions.get(&b).copied().unwrap_or(0), b))
+            .collect();
+        labels.sort();
+        for &(position, block) in labels.iter().rev() {
+            let label = format!("{}:", decompiler.label(block));
+            decompiler.out.insert(position, (0, label));
+        }
+
+        // every register the output mentions that isn't an argument
+        let used: HashSet<&str> = decompiler
+            .out
+            .iter()
+            .flat_map(|(_, line)| line.split(|c: char| !c.is_ascii_alphanumeric()))
+            .collect();
+        let params: Vec<String> = (12..12 + own.params as u8)
+            .map(|r| format!("uint16_t {}", reg_name(r)))
+            .collect();
+        let locals: Vec<String> = (4..16)
+            .filter(|&r| !(12..12 + own.params as u8).contains(&r))
+            .map(reg_name)
+            .filter(|name| used.contains(name.as_str()))
+            .collect();
+
+        if let Some(prototype) = &function.signature {
+            text += &format!("\n// {prototype}");
+        }
+        text += &format!(
+            "\n{} {}({})\n{{\n",
+            if own.returns { "uint16_t" } else { "void" },
+            own.name,
+            if params.is_empty() {
+                "void".to_owned()
+            } else {
+                params.join(", ")
+            }
+        );
+        if !locals.is_empty() {
+            text += &format!("{INDENT}uint16_t {};\n", locals.join(", "));
+        }
+        for (depth, line) in decompiler.out {
+            text += &format!("{}{line}\n", INDENT.repeat(depth));
+        }
+        text += "}\n";
+    }
+    text
+}
+
+pub fn print_decompiled(lines: &[Line], functions: &[Function], registers: &[Register]) {
+    print!("{}", decompile(lines, functions, registers));
+}
@@ -0,0 +1,324 @@
+/*
+    Peripheral registers of the parts we have memory maps for, and which of
+    the emulator's peripheral models (peripherals.rs) each one gets. The
+    listing names registers from the table of the selected device and the
+    emulator builds its bus from the same table, so an address is only ever
+    written down once. Anything in the peripheral range that isn't listed
+    still prints as a plain address.
+
+    The F1611 has no table yet: it names nothing and can't be emulated.
+*/
+
+pub struct Register {
+    pub address: u16,
+    pub name: &'static str,
+    pub byte: bool, // 8 bit register, 0x0010-0x00ff on the 1xx/2xx parts
+}
+
+const fn byte(address: u16, name: &'static str) -> Register {
+    Register {
+        address,
+        name,
+        byte: true,
+    }
+}
+
+const fn word(address: u16, name: &'static str) -> Register {
+    Register {
+        address,
+        name,
+        byte: false,
+    }
+}
+
+// a peripheral model and what tells one instance of it from another, vectors count from 0xffe0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    // WDT+, WDTCTL with its flag in IFG1
+    Watchdog {
+        vector: u16,
+    },
+    // Timer_A3 whose registers are called prefix + CTL, R, IV, CCTLn and CCRn
+    TimerA {
+        prefix: &'static str,
+        ccr0_vector: u16,
+        vector: u16,
+    },
+    // 2xx style ports, PxIN to PxREN at consecutive addresses, with the port's vector
+    Gpio {
+        ports: &'static [(&'static str, u16)],
+    },
+    // USCI_A0 as a UART, flags in IFG2
+    Usci {
+        rx_vector: u16,
+        tx_vector: u16,
+    },
+    // eUSCI_A0 as a UART, its own UCA0IE, UCA0IFG and UCA0IV and one vector
+    Eusci {
+        vector: u16,
+    },
+}
+
+pub struct Device {
+    pub name: &'static str,
+    pub registers: &'static [Register],
+    pub models: &'static [Model],
+}
+
+impl Device {
+    pub fn register_at(&self, address: u16) -> Option<&'static Register> {
+        self.registers.iter().find(|r| r.address == address)
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.registers
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| r.address)
+    }
+}
+
+pub fn device(name: &str) -> Option<&'static Device> {
+    DEVICES.iter().find(|device| device.name == name)
+}
+
+pub const DEVICES: &[Device] = &[
+    Device {
+        name: "msp430g2553",
+        registers: MSP430G2553,
+        models: &[
+            Model::Watchdog { vector: 10 },
+            Model::TimerA {
+                prefix: "TA0",
+                ccr0_vector: 9,
+                vector: 8,
+            },
+            Model::Gpio {
+                ports: &[("P1IN", 2), ("P2IN", 3)],
+            },
+            Model::Usci {
+                rx_vector: 7,
+                tx_vector: 6,
+            },
+        ],
+    },
+    Device {
+        name: "msp430f2274",
+        registers: MSP430F2274,
+        models: &[
+            Model::Watchdog { vector: 10 },
+            Model::TimerA {
+                prefix: "TA",
+                ccr0_vector: 9,
+                vector: 8,
+            },
+            Model::Gpio {
+                ports: &[("P1IN", 2), ("P2IN", 3)],
+            },
+            Model::Usci {
+                rx_vector: 7,
+                tx_vector: 6,
+            },
+        ],
+    },
+    // WDT_A and the ports are laid out differently from the 2xx ones, they're plain memory
+    Device {
+        name: "msp430fr5969",
+        registers: MSP430FR5969,
+        models: &[
+            Model::TimerA {
+                prefix: "TA0",
+                ccr0_vector: 5,
+                vector: 4,
+            },
+            Model::Eusci { vector: 8 },
+        ],
+    },
+];
+
+pub const MSP430G2553: &[Register] = &[
+    // special function registers
+    byte(0x0000, "IE1"),
+    byte(0x0001, "IE2"),
+    byte(0x0002, "IFG1"),
+    byte(0x0003, "IFG2"),
+    // digital I/O
+    byte(0x0020, "P1IN"),
+    byte(0x0021, "P1OUT"),
+    byte(0x0022, "P1DIR"),
+    byte(0x0023, "P1IFG"),
+    byte(0x0024, "P1IES"),
+    byte(0x0025, "P1IE"),
+    byte(0x0026, "P1SEL"),
+    byte(0x0027, "P1REN"),
+    byte(0x0028, "P2IN"),
+    byte(0x0029, "P2OUT"),
+    byte(0x002a, "P2DIR"),
+    byte(0x002b, "P2IFG"),
+    byte(0x002c, "P2IES"),
+    byte(0x002d, "P2IE"),
+    byte(0x002e, "P2SEL"),
+    byte(0x002f, "P2REN"),
+    byte(0x0041, "P1SEL2"),
+    byte(0x0042, "P2SEL2"),
+    // basic clock
+    byte(0x0053, "BCSCTL3"),
+    byte(0x0056, "DCOCTL"),
+    byte(0x0057, "BCSCTL1"),
+    byte(0x0058, "BCSCTL2"),
+    // USCI_A0
+    byte(0x0060, "UCA0CTL0"),
+    byte(0x0061, "UCA0CTL1"),
+    byte(0x0062, "UCA0BR0"),
+    byte(0x0063, "UCA0BR1"),
+    byte(0x0064, "UCA0MCTL"),
+    byte(0x0065, "UCA0STAT"),
+    byte(0x0066, "UCA0RXBUF"),
+    byte(0x0067, "UCA0TXBUF"),
+    // watchdog and flash
+    word(0x0120, "WDTCTL"),
+    word(0x0128, "FCTL1"),
+    word(0x012a, "FCTL2"),
+    word(0x012c, "FCTL3"),
+    // Timer0_A3
+    word(0x012e, "TA0IV"),
+    word(0x0160, "TA0CTL"),
+    word(0x0162, "TA0CCTL0"),
+    word(0x0164, "TA0CCTL1"),
+    word(0x0166, "TA0CCTL2"),
+    word(0x0170, "TA0R"),
+    word(0x0172, "TA0CCR0"),
+    word(0x0174, "TA0CCR1"),
+    word(0x0176, "TA0CCR2"),
+    // ADC10
+    word(0x01b0, "ADC10CTL0"),
+    word(0x01b2, "ADC10CTL1"),
+    word(0x01b4, "ADC10MEM"),
+];
+
+pub const MSP430F2274: &[Register] = &[
+    // special function registers
+    byte(0x0000, "IE1"),
+    byte(0x0001, "IE2"),
+    byte(0x0002, "IFG1"),
+    byte(0x0003, "IFG2"),
+    // digital I/O
+    byte(0x0010, "P3REN"),
+    byte(0x0011, "P4REN"),
+    byte(0x0018, "P3IN"),
+    byte(0x0019, "P3OUT"),
+    byte(0x001a, "P3DIR"),
+    byte(0x001b, "P3SEL"),
+    byte(0x001c, "P4IN"),
+    byte(0x001d, "P4OUT"),
+    byte(0x001e, "P4DIR"),
+    byte(0x001f, "P4SEL"),
+    byte(0x0020, "P1IN"),
+    byte(0x0021, "P1OUT"),
+    byte(0x0022, "P1DIR"),
+    byte(0x0023, "P1IFG"),
+    byte(0x0024, "P1IES"),
+    byte(0x0025, "P1IE"),
+    byte(0x0026, "P1SEL"),
+    byte(0x0027, "P1REN"),
+    byte(0x0028, "P2IN"),
+    byte(0x0029, "P2OUT"),
+    byte(0x002a, "P2DIR"),
+    byte(0x002b, "P2IFG"),
+    byte(0x002c, "P2IES"),
+    byte(0x002d, "P2IE"),
+    byte(0x002e, "P2SEL"),
+    byte(0x002f, "P2REN"),
+    // basic clock
+    byte(0x0053, "BCSCTL3"),
+    byte(0x0056, "DCOCTL"),
+    byte(0x0057, "BCSCTL1"),
+    byte(0x0058, "BCSCTL2"),
+    // USCI_A0
+    byte(0x0060, "UCA0CTL0"),
+    byte(0x0061, "UCA0CTL1"),
+    byte(0x0062, "UCA0BR0"),
+    byte(0x0063, "UCA0BR1"),
+    byte(0x0064, "UCA0MCTL"),
+    byte(0x0065, "UCA0STAT"),
+    byte(0x0066, "UCA0RXBUF"),
+    byte(0x0067, "UCA0TXBUF"),
+    // watchdog and flash
+    word(0x0120, "WDTCTL"),
+    word(0x0128, "FCTL1"),
+    word(0x012a, "FCTL2"),
+    word(0x012c, "FCTL3"),
+    // Timer_A3, no 0 in the names on this one
+    word(0x012e, "TAIV"),
+    word(0x0160, "TACTL"),
+    word(0x0162, "TACCTL0"),
+    word(0x0164, "TACCTL1"),
+    word(0x0166, "TACCTL2"),
+    word(0x0170, "TAR"),
+    word(0x0172, "TACCR0"),
+    word(0x0174, "TACCR1"),
+    word(0x0176, "TACCR2"),
+    // Timer_B3
+    word(0x011e, "TBIV"),
+    word(0x0180, "TBCTL"),
+    word(0x0182, "TBCCTL0"),
+    word(0x0184, "TBCCTL1"),
+    word(0x0186, "TBCCTL2"),
+    word(0x0190, "TBR"),
+    word(0x0192, "TBCCR0"),
+    word(0x0194, "TBCCR1"),
+    word(0x0196, "TBCCR2"),
+    // ADC10
+    word(0x01b0, "ADC10CTL0"),
+    word(0x01b2, "ADC10CTL1"),
+    word(0x01b4, "ADC10MEM"),
+];
+
+pub const MSP430FR5969: &[Register] = &[
+    // SFRs, power management and watchdog
+    word(0x0100, "SFRIE1"),
+    word(0x0102, "SFRIFG1"),
+    word(0x0120, "PMMCTL0"),
+    word(0x0130, "PM5CTL0"),
+    word(0x015c, "WDTCTL"),
+    // ports 1 and 2, interleaved a byte apart
+    byte(0x0200, "P1IN"),
+    byte(0x0201, "P2IN"),
+    byte(0x0202, "P1OUT"),
+    byte(0x0203, "P2OUT"),
+    byte(0x0204, "P1DIR"),
+    byte(0x0205, "P2DIR"),
+    byte(0x0206, "P1REN"),
+    byte(0x0207, "P2REN"),
+    byte(0x020a, "P1SEL0"),
+    byte(0x020b, "P2SEL0"),
+    byte(0x020c, "P1SEL1"),
+    byte(0x020d, "P2SEL1"),
+    byte(0x0218, "P1IES"),
+    byte(0x0219, "P2IES"),
+    byte(0x021a, "P1IE"),
+    byte(0x021b, "P2IE"),
+    byte(0x021c, "P1IFG"),
+    byte(0x021d, "P2IFG"),
+    // Timer0_A3
+    word(0x0340, "TA0CTL"),
+    word(0x0342, "TA0CCTL0"),
+    word(0x0344, "TA0CCTL1"),
+    word(0x0346, "TA0CCTL2"),
+    word(0x0350, "TA0R"),
+    word(0x0352, "TA0CCR0"),
+    word(0x0354, "TA0CCR1"),
+    word(0x0356, "TA0CCR2"),
+    word(0x0360, "TA0EX0"),
+    word(0x036e, "TA0IV"),
+    // eUSCI_A0
+    word(0x05c0, "UCA0CTLW0"),
+    word(0x05c6, "UCA0BRW"),
+    word(0x05c8, "UCA0MCTLW"),
+    word(0x05ca, "UCA0STATW"),
+    word(0x05cc, "UCA0RXBUF"),
+    word(0x05ce, "UCA0TXBUF"),
+    word(0x05da, "UCA0IE"),
+    word(0x05dc, "UCA0IFG"),
+    word(0x05de, "UCA0IV"),
+];
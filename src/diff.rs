@@ -0,0 +1,279 @@
+use std::ops::Range;
+
+use crate::{
+    annotations::Annotations,
+    functions::find_functions_from,
+    globals::Line,
+    ir::{lift, Ir, Operand, Width},
+    memory::MemoryMap,
+    stats::mnemonic,
+};
+
+/*
+    Images are cut into functions the same way the listing finds them,
+    annotations included, and a function is only its code: data and padding
+    between functions never take part. Functions are paired up by label
+    when they have one (from each image's own annotations), otherwise by
+    comparing their bodies with everything that only moves when code is
+    inserted elsewhere blanked out: call targets, jump offsets, and
+    immediates, absolute and symbolic operands that point into the image
+    (BR #addr, MOV #func, r12, MOV &table, r12). Each pair is then diffed
+    instruction by instruction with the same comparison.
+
+    The diff is Hirschberg's: an LCS in linear space, so a big image with
+    long functions doesn't need an n*m table per pair.
+*/
+
+pub struct Chunk<'l> {
+    pub name: String,
+    pub label: Option<String>,
+    pub lines: Vec<&'l Line>, // in address order
+    pub image: Range<u32>,    // CPU addresses the whole image covers
+}
+
+// what functions are lined up by, two labels with the same name are the same function
+#[derive(Debug, PartialEq)]
+pub enum Key {
+    Label(String),
+    Body(Vec<String>),
+}
+
+impl Chunk<'_> {
+    pub fn fingerprint(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .map(|line| normalize(line, &self.image))
+            .collect()
+    }
+
+    pub fn key(&self) -> Key {
+        match &self.label {
+            Some(label) => Key::Label(label.clone()),
+            None => Key::Body(self.fingerprint()),
+        }
+    }
+}
+
+// every function's code lines, including what a code region claimed for it
+pub fn split_functions<'l>(
+    lines: &'l [Line],
+    bytes: &[u8],
+    notes: &Annotations,
+    map: &MemoryMap,
+) -> Vec<Chunk<'l>> {
+    let base = map.image_base;
+    let starts = notes.function_starts();
+    let mut functions = find_functions_from(lines, bytes, base, &starts, map.vectors());
+    notes.apply(lines, &mut functions);
+
+    functions
+        .iter()
+        .map(|f| {
+            let mut indexes: Vec<usize> = f.body.iter().chain(&f.claimed).copied().collect();
+            indexes.sort();
+            indexes.dedup();
+            Chunk {
+                name: f.name(lines),
+                label: f.label.clone(),
+                lines: indexes.iter().map(|&i| &lines[i]).collect(),
+                image: base..base + bytes.len() as u32,
+            }
+        })
+        .collect()
+}
+
+// what's left of an instruction once addresses that shift with the layout are dropped
+fn normalize(line: &Line, image: &Range<u32>) -> String {
+    let operand = |operand: Operand| match operand {
+        Operand::Imm(value) if image.contains(&value.into()) => "#<image>".to_owned(),
+        Operand::Absolute(address) if image.contains(&address.into()) => "&<image>".to_owned(),
+        Operand::Imm(value) => format!("#{value:#x}"),
+        Operand::Absolute(address) => format!("&{address:#x}"),
+        Operand::Reg(reg) => format!("r{reg}"),
+        Operand::Indexed(reg, offset) => format!("{offset:#x}(r{reg})"),
+        Operand::Indirect(reg) => format!("@r{reg}"),
+        Operand::IndirectIncrement(reg) => format!("@r{reg}+"),
+    };
+    let suffix = |width: Width| match width {
+        Width::Byte => ".b",
+        Width::Word => "",
+    };
+    match lift(line) {
+        Ir::Alu {
+            op,
+            width,
+            src,
+            dst,
+        } => format!("{op:?}{} {}, {}", suffix(width), operand(src), operand(dst)),
+        Ir::Push { width, src } => format!("PUSH{} {}", suffix(width), operand(src)),
+        Ir::Call { target } => format!("CALL {}", operand(target)),
+        Ir::Reti => "RETI".to_owned(),
+        Ir::Jump { .. } => mnemonic(&line.instruction),
+        Ir::Invalid => format!("{}", line.instruction),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+// longest common subsequence of the two, as the edits that turn old into new
+pub fn lcs<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    hirschberg(old, new, (0, 0), &mut edits);
+    edits
+}
+
+// `at` is where old and new start in the whole sequences
+fn hirschberg<T: PartialEq>(old: &[T], new: &[T], at: (usize, usize), edits: &mut Vec<Edit>) {
+    // matching ends first, they're most of any real diff
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let (i, j) = (at.0 + prefix, at.1 + prefix);
+    edits.extend((0..prefix).map(|k| Edit::Same(at.0 + k, at.1 + k)));
+
+    match (old.len(), new.len()) {
+        (0, _) => edits.extend((0..new.len()).map(|k| Edit::Added(j + k))),
+        (_, 0) => edits.extend((0..old.len()).map(|k| Edit::Removed(i + k))),
+        (1, _) => match new.iter().position(|item| *item == old[0]) {
+            Some(p) => {
+                edits.extend((0..p).map(|k| Edit::Added(j + k)));
+                edits.push(Edit::Same(i, j + p));
+                edits.extend((p + 1..new.len()).map(|k| Edit::Added(j + k)));
+            }
+            None => {
+                edits.extend((0..new.len()).map(|k| Edit::Added(j + k)));
+                edits.push(Edit::Removed(i));
+            }
+        },
+        _ => {
+            // split old in half and new wherever the two halves' LCS add up to the most
+            let half = old.len() / 2;
+            let forward = lcs_lengths(&old[..half], new, false);
+            let backward = lcs_lengths(&old[half..], new, true);
+            let cut = (0..=new.len())
+                .rev()
+                .max_by_key(|&k| forward[k] + backward[new.len() - k])
+                .unwrap_or(0);
+            hirschberg(&old[..half], &new[..cut], (i, j), edits);
+            hirschberg(&old[half..], &new[cut..], (i + half, j + cut), edits);
+        }
+    }
+
+    let (i, j) = (i + old.len(), j + new.len());
+    edits.extend((0..suffix).map(|k| Edit::Same(i + k, j + k)));
+}
+
+// LCS length of old against every prefix of new (or suffix, reversed), a row at a time
+fn lcs_lengths<T: PartialEq>(old: &[T], new: &[T], reversed: bool) -> Vec<usize> {
+    let pick = |items: &[T], k: usize| match reversed {
+        true => items.len() - 1 - k,
+        false => k,
+    };
+    let mut row = vec![0; new.len() + 1];
+    let mut next = vec![0; new.len() + 1];
+    for i in 0..old.len() {
+        let a = &old[pick(old, i)];
+        for j in 0..new.len() {
+            next[j + 1] = match *a == new[pick(new, j)] {
+                true => row[j] + 1,
+                false => next[j].max(row[j + 1]),
+            };
+        }
+        std::mem::swap(&mut row, &mut next);
+    }
+    row
+}
+
+// both images are at the map's base, each with its own annotations
+pub fn diff_images(
+    old: (&[Line], &[u8], &Annotations),
+    new: (&[Line], &[u8], &Annotations),
+    map: &MemoryMap,
+) -> String {
+    let old_functions = split_functions(old.0, old.1, old.2, map);
+    let new_functions = split_functions(new.0, new.1, new.2, map);
+    let old_keys: Vec<Key> = old_functions.iter().map(Chunk::key).collect();
+    let new_keys: Vec<Key> = new_functions.iter().map(Chunk::key).collect();
+
+    let mut text = String::new();
+    let mut unchanged = 0;
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    // matching functions anchor the alignment, whatever is left between two anchors was modified
+    let mut edits = lcs(&old_keys, &new_keys);
+    edits.push(Edit::Same(old_functions.len(), new_functions.len()));
+    for edit in edits {
+        match edit {
+            Edit::Removed(i) => removed.push(i),
+            Edit::Added(j) => added.push(j),
+            Edit::Same(i, j) => {
+                let pairs = removed.len().min(added.len());
+                for (&i, &j) in removed.iter().zip(added.iter()) {
+                    text += &function_diff(&old_functions[i], &new_functions[j]);
+                }
+                for &i in &removed[pairs..] {
+                    text += &format!("removed {}\n", old_functions[i].name);
+                }
+                for &j in &added[pairs..] {
+                    text += &format!("added {}\n", new_functions[j].name);
+                }
+                removed.clear();
+                added.clear();
+
+                // a label lines up a function that changed too
+                let (Some(old), Some(new)) = (old_functions.get(i), new_functions.get(j)) else {
+                    continue;
+                };
+                match old.fingerprint() == new.fingerprint() {
+                    true => unchanged += 1,
+                    false => text += &function_diff(old, new),
+                }
+            }
+        }
+    }
+    text + &format!("{unchanged} functions unchanged\n")
+}
+
+pub fn print_diff(
+    old: (&[Line], &[u8], &Annotations),
+    new: (&[Line], &[u8], &Annotations),
+    map: &MemoryMap,
+) {
+    print!("{}", diff_images(old, new, map));
+}
+
+fn function_diff(old: &Chunk, new: &Chunk) -> String {
+    let mut text = format!("@@ {} -> {} @@\n", old.name, new.name);
+    for edit in lcs(&old.fingerprint(), &new.fingerprint()) {
+        text += &match edit {
+            Edit::Same(i, j) => {
+                let (old, new) = (old.lines[i], new.lines[j]);
+                format!(
+                    "  {:04x} {:04x}   {}\n",
+                    old.address.0, new.address.0, new.instruction
+                )
+            }
+            Edit::Removed(i) => {
+                let old = old.lines[i];
+                format!("- {:04x}        {}\n", old.address.0, old.instruction)
+            }
+            Edit::Added(j) => {
+                let new = new.lines[j];
+                format!("+      {:04x}   {}\n", new.address.0, new.instruction)
+            }
+        };
+    }
+    text
+}
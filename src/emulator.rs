@@ -0,0 +1,423 @@
+use crate::{
+    cycles::{cycles, Cpu},
+    decoder::line_at,
+    globals::{Line, PC, SP, SR, ZR},
+    ir::{alu, condition_holds, lift_at, Ir, Operand, Width, C, CPUOFF, GIE},
+    peripherals::Peripheral,
+};
+
+/*
+    Runs lifted instructions one at a time against a flat 64 KB address
+    space. Fetch and decode are the disassembler's own: the words at PC go
+    through the decoder like they would in a listing, then ir::lift_at
+    turns them into something to execute.
+
+    Data accesses the CPU makes go past the peripherals (see peripherals.rs)
+    and every instruction's cycles tick them. read_byte and friends are the
+    host's view, they never have side effects. A machine from new() has no
+    peripherals at all, addresses below 0x0200 are then plain memory.
+*/
+
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const VECTORS: u16 = 0xffe0;
+pub const INTERRUPT_CYCLES: u64 = 6; // push PC, push SR, load the vector
+pub const SLEEP_CYCLES: u64 = 1; // how far time moves on per step while the CPU is off
+pub const SLEEP_LIMIT: u64 = 1 << 24; // run gives up on a CPU asleep this long
+
+#[derive(Debug, PartialEq)]
+pub enum Fault {
+    Invalid(u16), // PC of something we can't execute
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Ran,       // executed one instruction
+    Interrupt, // took an interrupt instead
+    Sleeping,  // CPUOFF is set and nothing is pending
+    Reset,     // a peripheral (the watchdog) reset the chip
+}
+
+// one step of Machine::run, with what the CPU looked like going in
+pub struct Stepped {
+    pub pc: u16,
+    pub line: Line, // what was at pc
+    pub regs: [u16; 16],
+    pub cycles: u64, // spent by this step
+    pub step: Step,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Done,        // ran all the steps asked for
+    Asleep(u16), // CPU off for SLEEP_LIMIT cycles with nothing pending, at this PC
+    Fault(Fault),
+}
+
+pub struct Machine {
+    pub regs: [u16; 16],
+    pub memory: Vec<u8>,
+    pub cycles: u64,
+    pub pending: u16, // bit n set means vector n wants service
+    pub cpu: Cpu,     // for the cycle counts
+    pub peripherals: Vec<Box<dyn Peripheral>>,
+    pub image_base: u32, // where load_image put the image, reset starts there without a vector
+}
+
+// where a lifted operand lives once the registers it uses are known
+#[derive(Clone, Copy)]
+enum Location {
+    Reg(u8),
+    Memory(u16),
+    Value(u16),
+}
+
+impl Default for Machine {
+    fn default() -> Machine {
+        Machine::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            regs: [0; 16],
+            memory: vec![0; MEMORY_SIZE],
+            cycles: 0,
+            pending: 0,
+            cpu: Cpu::MSP430,
+            peripherals: Vec::new(),
+            image_base: 0,
+        }
+    }
+
+    pub fn with_peripherals(peripherals: Vec<Box<dyn Peripheral>>) -> Machine {
+        Machine {
+            peripherals,
+            ..Machine::new()
+        }
+    }
+
+    // the image goes where the listing says it is, at the memory map's image base
+    pub fn load_image(&mut self, bytes: &[u8], base: u32) {
+        let start = (base as usize).min(MEMORY_SIZE);
+        let end = (start + bytes.len()).min(MEMORY_SIZE);
+        self.memory[start..end].copy_from_slice(&bytes[..end - start]);
+        self.image_base = base;
+    }
+
+    // PC from the reset vector, or the start of the image if it doesn't reach it.
+    // memory survives, like RAM does through a watchdog reset
+    pub fn reset(&mut self) {
+        self.regs = [0; 16];
+        self.pending = 0;
+        for peripheral in &mut self.peripherals {
+            peripheral.reset(&mut self.memory);
+        }
+        self.regs[PC as usize] = match self.read_word(0xfffe) {
+            0xffff | 0x0000 => self.image_base as u16,
+            vector => vector,
+        };
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+    pub fn read_word(&self, address: u16) -> u16 {
+        let address = (address & !1) as usize; // word accesses ignore the low bit
+        u16::from_le_bytes([self.memory[address], self.memory[address + 1]])
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+    pub fn write_word(&mut self, address: u16, value: u16) {
+        let address = (address & !1) as usize;
+        self.memory[address..address + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // the CPU's accesses, the peripheral that owns the address hears about them
+    fn read(&mut self, address: u16, width: Width) -> u16 {
+        for peripheral in &mut self.peripherals {
+            if peripheral.owns(address) {
+                peripheral.read(address, &mut self.memory);
+            }
+        }
+        match width {
+            Width::Byte => self.read_byte(address) as u16,
+            Width::Word => self.read_word(address),
+        }
+    }
+    fn write(&mut self, address: u16, width: Width, value: u16) {
+        match width {
+            Width::Byte => self.write_byte(address, value as u8),
+            Width::Word => self.write_word(address, value),
+        }
+        for peripheral in &mut self.peripherals {
+            if peripheral.owns(address) {
+                peripheral.write(address, &mut self.memory);
+            }
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.regs[PC as usize]
+    }
+    pub fn sr(&self) -> u16 {
+        self.regs[SR as usize]
+    }
+
+    // r3 always reads as the constant the addressing mode picked, writes to it vanish
+    fn set_reg(&mut self, reg: u8, value: u16) {
+        match reg {
+            ZR => (),
+            PC | SP => self.regs[reg as usize] = value & !1,
+            _ => self.regs[reg as usize] = value,
+        }
+    }
+
+    pub fn push(&mut self, value: u16) {
+        let sp = self.regs[SP as usize].wrapping_sub(2);
+        self.regs[SP as usize] = sp;
+        self.write_word(sp, value);
+    }
+    pub fn pop(&mut self) -> u16 {
+        let sp = self.regs[SP as usize];
+        self.regs[SP as usize] = sp.wrapping_add(2);
+        self.read_word(sp)
+    }
+
+    // the instruction at address, decoded the same way the listing does it
+    pub fn decode(&self, address: u16) -> Line {
+        // every even address in 64 KB has a word to decode
+        line_at(&self.memory, 0, (address & !1) as usize).unwrap()
+    }
+
+    // works out addresses and applies @Rn+, so the operand can be read and written after
+    fn locate(&mut self, operand: Operand, width: Width) -> Location {
+        match operand {
+            Operand::Reg(reg) => Location::Reg(reg),
+            Operand::Imm(value) => Location::Value(value),
+            Operand::Absolute(address) => Location::Memory(address),
+            Operand::Indexed(reg, offset) => {
+                Location::Memory(self.regs[reg as usize].wrapping_add(offset))
+            }
+            Operand::Indirect(reg) => Location::Memory(self.regs[reg as usize]),
+            Operand::IndirectIncrement(reg) => {
+                let address = self.regs[reg as usize];
+                let step = if reg == SP { 2 } else { width.bytes() };
+                self.set_reg(reg, address.wrapping_add(step));
+                Location::Memory(address)
+            }
+        }
+    }
+
+    fn load(&mut self, location: Location, width: Width) -> u16 {
+        match location {
+            Location::Reg(reg) => self.regs[reg as usize] & width.mask(),
+            Location::Memory(address) => self.read(address, width),
+            Location::Value(value) => value & width.mask(),
+        }
+    }
+
+    // byte writes to a register clear its upper byte
+    fn store(&mut self, location: Location, width: Width, value: u16) {
+        match location {
+            Location::Reg(reg) => self.set_reg(reg, value & width.mask()),
+            Location::Memory(address) => self.write(address, width, value),
+            Location::Value(_) => (), // MOV x, #n, nothing happens
+        }
+    }
+
+    pub fn interrupt(&mut self, vector: u8) {
+        self.pending |= 1 << vector;
+    }
+
+    // highest priority first, only the NMI (14) and reset (15) ignore GIE
+    fn take_interrupt(&mut self) -> bool {
+        let requests = self
+            .peripherals
+            .iter()
+            .fold(self.pending, |requests, peripheral| {
+                requests | peripheral.requests(&self.memory)
+            });
+        let maskable = match self.sr() & GIE {
+            0 => requests & 0xc000,
+            _ => requests,
+        };
+        if maskable == 0 {
+            return false;
+        }
+        let vector = 15 - maskable.leading_zeros() as u16;
+        self.pending &= !(1 << vector);
+        for peripheral in &mut self.peripherals {
+            peripheral.acknowledge(vector, &mut self.memory);
+        }
+        self.push(self.pc());
+        self.push(self.sr());
+        self.regs[SR as usize] = 0; // GIE, CPUOFF and the flags all clear
+        self.regs[PC as usize] = self.read_word(VECTORS + vector * 2);
+        true
+    }
+
+    // time moves on for the peripherals, true if one of them reset the chip
+    fn elapse(&mut self, cycles: u64) -> bool {
+        self.cycles += cycles;
+        let mut reset = false;
+        for peripheral in &mut self.peripherals {
+            peripheral.tick(cycles, &mut self.memory);
+            reset |= peripheral.wants_reset();
+        }
+        if reset {
+            self.reset();
+        }
+        reset
+    }
+
+    pub fn step(&mut self) -> Result<Step, Fault> {
+        if self.take_interrupt() {
+            return Ok(match self.elapse(INTERRUPT_CYCLES) {
+                true => Step::Reset,
+                false => Step::Interrupt,
+            });
+        }
+        if self.sr() & CPUOFF != 0 {
+            return Ok(match self.elapse(SLEEP_CYCLES) {
+                true => Step::Reset,
+                false => Step::Sleeping,
+            });
+        }
+
+        let pc = self.pc();
+        let line = self.decode(pc);
+        let ir = lift_at(&line, pc);
+        self.regs[PC as usize] = pc.wrapping_add(line.words.0.len() as u16 * 2);
+        let cycles = cycles(&line.raw, self.cpu).unwrap_or(1) as u64;
+
+        match ir {
+            Ir::Invalid => {
+                self.regs[PC as usize] = pc;
+                return Err(Fault::Invalid(pc));
+            }
+            Ir::Alu {
+                op,
+                width,
+                src,
+                dst,
+            } => {
+                let src_location = self.locate(src, width);
+                // single operand instructions read and write the same place
+                let dst_location = match src == dst {
+                    true => src_location,
+                    false => self.locate(dst, width),
+                };
+                let s = self.load(src_location, width);
+                let d = match op.reads_destination() {
+                    true => self.load(dst_location, width),
+                    false => 0,
+                };
+                let carry = self.sr() & C != 0;
+                let result = alu(op, width, s, d, carry);
+                self.regs[SR as usize] = result.apply(self.sr());
+                if op.writes_result() {
+                    self.store(dst_location, width, result.value);
+                }
+            }
+            Ir::Push { width, src } => {
+                let location = self.locate(src, width);
+                let value = self.load(location, width);
+                let sp = self.regs[SP as usize].wrapping_sub(2);
+                self.regs[SP as usize] = sp;
+                self.write(sp, width, value);
+            }
+            Ir::Call { target } => {
+                let location = self.locate(target, Width::Word);
+                let target = self.load(location, Width::Word);
+                self.push(self.pc());
+                self.set_reg(PC, target);
+            }
+            Ir::Reti => {
+                self.regs[SR as usize] = self.pop();
+                let pc = self.pop();
+                self.set_reg(PC, pc);
+            }
+            Ir::Jump { condition, target } => {
+                if condition_holds(condition, self.sr()) {
+                    self.set_reg(PC, target);
+                }
+            }
+        }
+        Ok(match self.elapse(cycles) {
+            true => Step::Reset,
+            false => Step::Ran,
+        })
+    }
+
+    // step until `steps` have run, sleeping steps are passed on but don't use any up
+    pub fn run(&mut self, steps: usize, mut each: impl FnMut(&Machine, Stepped)) -> Stop {
+        let mut ran = 0;
+        let mut asleep_since = None;
+        while ran < steps {
+            let pc = self.pc();
+            let line = self.decode(pc);
+            let regs = self.regs;
+            let before = self.cycles;
+            let step = match self.step() {
+                Ok(step) => step,
+                Err(fault) => return Stop::Fault(fault),
+            };
+            let sleeping = step == Step::Sleeping;
+            let stepped = Stepped {
+                pc,
+                line,
+                regs,
+                cycles: self.cycles - before,
+                step,
+            };
+            each(self, stepped);
+            if sleeping {
+                let since = *asleep_since.get_or_insert(self.cycles);
+                if self.cycles - since > SLEEP_LIMIT {
+                    return Stop::Asleep(pc);
+                }
+                continue;
+            }
+            asleep_since = None;
+            ran += 1;
+        }
+        Stop::Done
+    }
+}
+
+// --step: run from reset and print every instruction with the registers it left behind
+pub fn print_steps(bytes: &[u8], base: u32, steps: usize, peripherals: Vec<Box<dyn Peripheral>>) {
+    let mut machine = Machine::with_peripherals(peripherals);
+    machine.load_image(bytes, base);
+    machine.reset();
+
+    let stop = machine.run(steps, |machine, stepped| {
+        let pc = stepped.pc;
+        match stepped.step {
+            Step::Ran => {
+                let changed: Vec<String> = (0..16)
+                    .filter(|&r| r != PC as usize && machine.regs[r] != stepped.regs[r])
+                    .map(|r| format!("r{r}={:04x}", machine.regs[r]))
+                    .collect();
+                println!(
+                    "{pc:04x}   {:<28} {:>6}   {}",
+                    stepped.line.instruction.to_string(),
+                    machine.cycles,
+                    changed.join(" ")
+                );
+            }
+            Step::Interrupt => println!("{pc:04x}   interrupt -> {:04x}", machine.pc()),
+            Step::Reset => println!("{pc:04x}   reset -> {:04x}", machine.pc()),
+            Step::Sleeping => (),
+        }
+    });
+    match stop {
+        Stop::Done => (),
+        Stop::Asleep(pc) => println!("{pc:04x}   sleeping with nothing pending"),
+        Stop::Fault(Fault::Invalid(pc)) => {
+            println!("{pc:04x}   can't execute {}", machine.decode(pc).words)
+        }
+    }
+}
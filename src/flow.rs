@@ -1,83 +1,136 @@
-use std::{collections::HashSet, ops::Add};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Add,
+};
 
 use crate::{
-    globals::{CurrentBinaryScope, Instruction, Offset, OneOpcode, Word},
+    globals::{AddressMode, Instruction, JmpOpcode, Line, OneOpcode, PC},
     pseudo::PsuedoOpcode,
 };
-use crossbeam_queue::SegQueue;
-use lazy_static::lazy_static;
 
-/*
-    LOGIC:
-        search for CALL, RET, and JEQ/JNE/etc
-        if CALL, save adress in a vector
-        when RET, pop the call vector and go to the adress
-        when JEQ/JNE/etc, save all posibilities to Queue vector
-*/
+// CALL #addr, or an indirect call constant propagation managed to resolve
+pub fn call_target(line: &Line) -> Option<Address> {
+    match line.instruction {
+        Instruction::ONE {
+            opcode: OneOpcode::CALL,
+            dam: AddressMode::IndirectIncrement,
+            dest,
+            dest_index: Some(word),
+            ..
+        } if dest.0 == PC => Address::code(word.0.into()),
+        Instruction::ONE {
+            opcode: OneOpcode::CALL,
+            ..
+        } => line.targets.first().copied(),
+        _ => None,
+    }
+}
 
-pub fn check_for_flow(
-    flowcontroller: &mut FlowController,
-    scope: &mut CurrentBinaryScope,
-    instruction: Instruction,
-) {
-    flowcontroller.add_to_blacklist(scope.address);
+// where a relative jump lands, the offset already accounts for the PC having moved past it
+pub fn jump_target(line: &Line) -> Option<Address> {
+    match line.instruction {
+        Instruction::JMP { offset, .. } => Some(line.address + offset.0),
+        _ => None,
+    }
+}
 
+// anything after these is only reachable if something else jumps to it
+pub fn ends_block(instruction: &Instruction) -> bool {
     match instruction {
-        Instruction::JMP { condition, offset } => {
-            flowcontroller.flow_add_to_queue_offset(scope, offset);
-        }
-        Instruction::PSEUDO {
-            dest_index,
-            opcode,
-            b,
-            dam,
-            dest,
-        } => {
-            if opcode == PsuedoOpcode::RET {
-                flowcontroller.flow_return(scope);
-            }
-        }
+        Instruction::Invalid => true, // the CPU would reset, or we can't decode what it does
+        Instruction::JMP { .. } => true,
         Instruction::ONE {
-            opcode,
-            b,
-            dam,
-            dest,
-            dest_index,
-        } => {
-            if opcode == OneOpcode::CALL {
-                flowcontroller.flow_call(scope, dest_index);
-            }
-        }
+            opcode: OneOpcode::RETI,
+            ..
+        } => true,
+        Instruction::PSEUDO {
+            opcode: PsuedoOpcode::RET | PsuedoOpcode::BR,
+            ..
+        } => true,
+        Instruction::TWO { dam, dest, .. } => *dam == AddressMode::Direct && dest.0 == PC,
+        _ => false,
+    }
+}
 
-        _ => (),
+// the only block end that can also fall through to the next instruction
+pub fn is_conditional(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::JMP { condition, .. } => !matches!(condition, JmpOpcode::JMP),
+        _ => false,
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy)]
-pub struct Address(pub u64);
+pub fn index_by_address(lines: &[Line]) -> HashMap<Address, usize> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (line.address, i))
+        .collect()
+}
 
-impl Address {
-    pub fn from_index(index: usize) -> Address {
-        //   println!("{index}");
-        Address(
-            ((index as u64 - 1 as u64) * 2)
-                .try_into()
-                .expect("for all the 128 bit machines ig"),
-        )
-    }
-    pub fn to_index(&self) -> usize {
-        (self.0 + 2 / 2) as usize
+// lines something other than falling through can get to
+pub fn block_starts(lines: &[Line]) -> HashSet<usize> {
+    let by_address = index_by_address(lines);
+    let mut starts = HashSet::from([0]);
+    for (i, line) in lines.iter().enumerate() {
+        if ends_block(&line.instruction) || is_conditional(&line.instruction) {
+            starts.insert(i + 1);
+        }
+        let targets = jump_target(line)
+            .into_iter()
+            .chain(line.targets.iter().copied());
+        starts.extend(targets.filter_map(|a| by_address.get(&a)));
     }
+    starts
 }
 
-impl CurrentBinaryScope<'_> {
-    pub fn jump_to_offset(&mut self, offset: Offset) {
-        self.index = (Address::from_index(self.index) + offset.0).to_index();
-        self.update();
+// lines control can reach from lines[index] without going through a call
+pub fn successors(
+    lines: &[Line],
+    index: usize,
+    by_address: &HashMap<Address, usize>,
+) -> Vec<usize> {
+    let mut next = Vec::new();
+    let instruction = &lines[index].instruction;
+
+    if (!ends_block(instruction) || is_conditional(instruction)) && index + 1 < lines.len() {
+        next.push(index + 1);
     }
-    pub fn jump_to_absolute(&mut self, mut address: Address) {
-        address.0 -= 0x8000;
-        self.index = address.to_index();
+    if let Some(target) = jump_target(&lines[index]).and_then(|a| by_address.get(&a)) {
+        next.push(*target);
+    }
+    // a resolved CALL keeps its target here too, but that's not flow within the function
+    if ends_block(instruction) {
+        for target in &lines[index].targets {
+            if let Some(&target) = by_address.get(target) {
+                next.push(target);
+            }
+        }
+    }
+    next
+}
+
+/*
+    An Address is a CPU address, up to 20 bits on the MSP430X. The image
+    base comes from the memory map and is handed to analyze, which places
+    every line as it decodes it, so nothing after that needs the base
+    again. Only code that goes back to the bytes (the decoder, data
+    classification, jump tables, the TUI's hex pane) turns an address
+    into a file offset, address - base.
+    Instructions are word aligned, so an odd address can't be a jump or
+    call target. Data can sit anywhere.
+*/
+
+pub const ADDRESS_LIMIT: u32 = 1 << 20; // everything the MSP430X can reach
+
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct Address(pub u32);
+
+impl Address {
+    // None for an odd address or past 20 bits, an instruction can't be there.
+    // whether anything is there is up to whoever looks it up
+    pub fn code(address: u32) -> Option<Address> {
+        (address < ADDRESS_LIMIT && address.is_multiple_of(2)).then_some(Address(address))
     }
 }
 
@@ -85,68 +138,20 @@ impl Add<u16> for Address {
     type Output = Address;
 
     fn add(self, rhs: u16) -> Self::Output {
-        Address(self.0 + rhs as u64)
+        Address(self.0 + rhs as u32)
     }
 }
 impl Add<usize> for Address {
     type Output = Address;
 
     fn add(self, rhs: usize) -> Self::Output {
-        Address(self.0 + rhs as u64)
+        Address(self.0 + rhs as u32)
     }
 }
 impl Add<i16> for Address {
     type Output = Address;
 
     fn add(self, rhs: i16) -> Self::Output {
-        Address(self.0 + rhs as u64)
-    }
-}
-
-pub struct FlowController {
-    pub BLACKLIST: HashSet<Address>,
-    pub BRANCH_QUEUE: Vec<Address>,
-    pub CALL_QUEUE: Vec<Address>,
-}
-
-impl FlowController {
-    pub fn new() -> FlowController {
-        FlowController {
-            BLACKLIST: HashSet::new(),
-            BRANCH_QUEUE: Vec::new(),
-            CALL_QUEUE: Vec::new(),
-        }
-    }
-
-    pub fn flow_add_to_queue_offset(&mut self, scope: &mut CurrentBinaryScope, offset: Offset) {
-        self.BRANCH_QUEUE
-            .push(Address::from_index(scope.index) + offset.0);
-    }
-    pub fn flow_call(&mut self, scope: &mut CurrentBinaryScope, dest_immediate: Option<Word>) {
-        match dest_immediate {
-            Some(word) => {
-                self.CALL_QUEUE.push(Address::from_index(scope.index + 1));
-                scope.jump_to_absolute(Address(word.0.into()));
-            }
-            _ => (),
-        }
-    }
-    pub fn flow_return(&mut self, scope: &mut CurrentBinaryScope) {
-        if !self.BRANCH_QUEUE.is_empty() {
-            while self
-                .BLACKLIST
-                .contains(&Address(self.BRANCH_QUEUE.pop().unwrap().0))
-            {}
-        } else {
-            if !self.CALL_QUEUE.is_empty() {
-                scope.jump_to_absolute(Address(self.CALL_QUEUE.pop().unwrap().0));
-            }
-        }
-    }
-
-    pub fn add_to_blacklist(&mut self, address: Address) {
-        if self.BLACKLIST.contains(&address) {
-            self.BLACKLIST.insert(address);
-        }
+        Address(self.0.wrapping_add_signed(rhs.into()))
     }
 }
@@ -0,0 +1,220 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::{
+    flow::{
+        call_target, ends_block, index_by_address, is_conditional, jump_target, successors, Address,
+    },
+    globals::{AddressMode, Instruction, Line, OneOpcode, TwoOpcode, SP},
+    jumptables::read_word,
+    memory::VECTOR_TABLE,
+    pseudo::PsuedoOpcode,
+};
+
+/*
+    Stripped images have no symbols, so functions are guessed from:
+        CALL #addr targets
+        the interrupt vector table, the part of it the image covers
+        msp430-gcc prologues (PUSH r10..r4, SUB #n, SP) right after a block end
+        code right after a RET that itself ends in a RET, for functions only called through pointers
+        whatever the annotations file says (see annotations.rs)
+    A function then owns every line reachable from its start without calls,
+    stopping at the start of any other function (tail calls).
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FunctionKind {
+    Entry,      // first instruction of the image
+    Called,     // CALL #addr target
+    Vector(u8), // interrupt vector 0-15, 15 is reset
+    Prologue,   // starts with register saves or a frame allocation
+    AfterReturn,
+    User, // from a code region or signature in the annotations
+}
+
+pub struct Function {
+    pub start: usize,        // index of the first line
+    pub end: usize,          // one past the last line of the body
+    pub body: Vec<usize>,    // every line reachable from start, in address order
+    pub claimed: Vec<usize>, // lines a code region gave it that nothing reaches, see annotations.rs
+    pub kind: FunctionKind,
+    pub label: Option<String>,     // a name somebody gave it
+    pub signature: Option<String>, // and a C prototype
+}
+
+impl Function {
+    pub fn name(&self, lines: &[Line]) -> String {
+        if let Some(label) = &self.label {
+            return label.clone();
+        }
+        let address = lines[self.start].address.0;
+        match self.kind {
+            FunctionKind::Vector(15) => format!("reset_{address:04x}"),
+            FunctionKind::Vector(_) => format!("isr_{address:04x}"),
+            _ => format!("sub_{address:04x}"),
+        }
+    }
+}
+
+// the 16 vectors in the table the memory map has, the ones the image covers
+pub fn vector_targets(bytes: &[u8], base: u32, table: Range<u32>) -> Vec<(u8, Address)> {
+    table
+        .step_by(2)
+        .enumerate()
+        .filter_map(|(vector, address)| Some((vector as u8, read_word(bytes, base, address)?)))
+        .filter(|&(_, word)| word != 0xffff)
+        .filter_map(|(vector, word)| Some((vector, Address::code(word.into())?)))
+        .collect()
+}
+
+fn is_prologue(instruction: &Instruction) -> bool {
+    match *instruction {
+        Instruction::ONE {
+            opcode: OneOpcode::PUSH,
+            dam: AddressMode::Direct,
+            dest,
+            ..
+        } => (4..=10).contains(&dest.0),
+        Instruction::TWO {
+            opcode: TwoOpcode::SUB,
+            dest,
+            dam: AddressMode::Direct,
+            ..
+        } => dest.0 == SP,
+        _ => false,
+    }
+}
+
+pub fn is_return(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::PSEUDO {
+            opcode: PsuedoOpcode::RET,
+            ..
+        } | Instruction::ONE {
+            opcode: OneOpcode::RETI,
+            ..
+        }
+    )
+}
+
+// nothing can fall through into the next line, so only a jump or call can reach it
+fn ends_unconditionally(instruction: &Instruction) -> bool {
+    ends_block(instruction) && !is_conditional(instruction)
+}
+
+// bytes and base are only needed for the vector table, which is where it is on every part
+pub fn find_functions(lines: &[Line], bytes: &[u8], base: u32) -> Vec<Function> {
+    find_functions_from(lines, bytes, base, &[], Some(VECTOR_TABLE))
+}
+
+// with extra starts nothing in the image points at, and the vectors where the memory map has them
+pub fn find_functions_from(
+    lines: &[Line],
+    bytes: &[u8],
+    base: u32,
+    user: &[Address],
+    vectors: Option<Range<u32>>,
+) -> Vec<Function> {
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    let by_address = index_by_address(lines);
+    let jump_targets: HashSet<Address> = lines.iter().filter_map(jump_target).collect();
+
+    // BTreeMap so functions come out in address order, and the strongest evidence wins
+    let mut starts: BTreeMap<usize, FunctionKind> = BTreeMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        let after_block_end = i > 0 && ends_unconditionally(&lines[i - 1].instruction);
+        if after_block_end
+            && !jump_targets.contains(&line.address)
+            && is_prologue(&line.instruction)
+        {
+            starts.insert(i, FunctionKind::Prologue);
+        }
+    }
+    for target in lines.iter().filter_map(call_target) {
+        if let Some(&i) = by_address.get(&target) {
+            starts.insert(i, FunctionKind::Called);
+        }
+    }
+    for (vector, target) in vectors.map_or(Vec::new(), |table| vector_targets(bytes, base, table)) {
+        if let Some(&i) = by_address.get(&target) {
+            starts.insert(i, FunctionKind::Vector(vector));
+        }
+    }
+    for target in user {
+        if let Some(&i) = by_address.get(target) {
+            starts.entry(i).or_insert(FunctionKind::User);
+        }
+    }
+    starts.entry(0).or_insert(FunctionKind::Entry);
+
+    let mut functions: Vec<Function> = starts
+        .iter()
+        .map(|(&start, &kind)| walk(lines, start, kind, &starts, &by_address))
+        .collect();
+
+    // pointer-only functions: unowned code after a RET that runs into a RET of its own
+    let mut owned: HashSet<usize> = functions.iter().flat_map(|f| f.body.clone()).collect();
+    for i in 1..lines.len() {
+        if owned.contains(&i)
+            || !is_return(&lines[i - 1].instruction)
+            || matches!(lines[i].raw, Instruction::Invalid)
+        {
+            continue;
+        }
+        let function = walk(lines, i, FunctionKind::AfterReturn, &starts, &by_address);
+        let returns = function
+            .body
+            .iter()
+            .any(|&j| is_return(&lines[j].instruction));
+        if returns && function.body.iter().all(|j| !owned.contains(j)) {
+            owned.extend(function.body.iter());
+            functions.push(function);
+        }
+    }
+
+    functions.sort_by_key(|f| f.start);
+    functions
+}
+
+fn walk(
+    lines: &[Line],
+    start: usize,
+    kind: FunctionKind,
+    starts: &BTreeMap<usize, FunctionKind>,
+    by_address: &HashMap<Address, usize>,
+) -> Function {
+    let mut seen = HashSet::new();
+    let mut queue = vec![start];
+    while let Some(i) = queue.pop() {
+        if !seen.insert(i) {
+            continue;
+        }
+        for next in successors(lines, i, by_address) {
+            if !starts.contains_key(&next) || next == start {
+                queue.push(next);
+            }
+        }
+    }
+
+    let mut body: Vec<usize> = seen.into_iter().collect();
+    body.sort();
+    Function {
+        start,
+        end: body.last().unwrap() + 1,
+        body,
+        claimed: Vec::new(),
+        kind,
+        label: None,
+        signature: None,
+    }
+}
+
+// the line index of every function start, for things that only care where functions begin
+pub fn function_starts(functions: &[Function]) -> HashMap<usize, &Function> {
+    functions.iter().map(|f| (f.start, f)).collect()
+}
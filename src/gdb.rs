@@ -0,0 +1,344 @@
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    emulator::{Fault, Machine, Step},
+    globals::PC,
+    peripherals::Peripheral,
+};
+
+/*
+    Just enough of the GDB remote serial protocol for msp430-elf-gdb to
+    `target remote localhost:<port>` against the emulator, like it would
+    against mspdebug's gdb server.
+
+    Packets are $data#checksum, acknowledged with + until gdb asks for
+    QStartNoAckMode. Registers go over the wire as 32 bits each, little
+    endian, r0-r15 in order, that's what msp430-elf-gdb expects. Anything
+    we don't understand gets the empty reply, which gdb takes as
+    "not supported" and works around.
+*/
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+const POLL_EVERY: usize = 4096; // instructions between checks for ctrl-c while running
+
+enum Reply {
+    Send(String),
+    Sent,  // already answered
+    Close, // gdb killed or detached
+}
+
+// what gdb talks to us over, a TcpStream or a buffer in the tests
+pub trait Link: Read + Write {
+    // a byte that has already arrived, None if reading one would wait
+    fn poll(&mut self) -> io::Result<Option<u8>>;
+}
+
+impl Link for TcpStream {
+    fn poll(&mut self) -> io::Result<Option<u8>> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = match self.read(&mut byte) {
+            Ok(1) => Ok(Some(byte[0])),
+            Ok(_) => Err(io::Error::from(ErrorKind::UnexpectedEof)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+pub struct Session<L: Link> {
+    pub link: L,
+    pub machine: Machine,
+    pub breakpoints: HashSet<u16>,
+    pub acks: bool,
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+// pairs of hex digits, going by bytes so anything that isn't ASCII is just an error
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match *pair {
+            [high, low] => Some(hex_digit(high)? << 4 | hex_digit(low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// "addr,len" in m and M packets
+fn address_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let address = u32::from_str_radix(address, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    Some((address as u16, length))
+}
+
+// registers in 32 bit little endian hex, gdb sends 16 bit ones for some targets so take both
+fn parse_register(text: &str) -> Option<u16> {
+    let bytes = decode_hex(text)?;
+    match bytes.len() {
+        2 | 4 => Some(u16::from_le_bytes([bytes[0], bytes[1]])),
+        _ => None,
+    }
+}
+
+impl<L: Link> Session<L> {
+    pub fn new(link: L, machine: Machine) -> Session<L> {
+        Session {
+            link,
+            machine,
+            breakpoints: HashSet::new(),
+            acks: true,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.link.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    // the next good packet's data, or None for a lone ctrl-c
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => (),
+                0x03 => return Ok(None),
+                _ => continue, // acks and line noise
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = decode_hex(std::str::from_utf8(&checksum).unwrap_or(""));
+            let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            let good = expected == Some(vec![sum]);
+            if self.acks {
+                self.link.write_all(if good { b"+" } else { b"-" })?;
+            }
+            // gdb sends it again after a -, and without acks there's nothing sensible to do with it
+            if good {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.link
+            .write_all(format!("${data}#{sum:02x}").as_bytes())?;
+        if self.acks {
+            // gdb should say +, a - means send it again
+            while self.read_byte()? == b'-' {
+                self.link
+                    .write_all(format!("${data}#{sum:02x}").as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    // has gdb sent a ctrl-c while we were running
+    fn interrupted(&mut self) -> io::Result<bool> {
+        Ok(self.link.poll()? == Some(0x03))
+    }
+
+    // one step, turned into the signal gdb should see if it stopped us
+    fn step(&mut self) -> Option<u8> {
+        match self.machine.step() {
+            Ok(Step::Ran | Step::Interrupt | Step::Sleeping | Step::Reset) => None,
+            Err(Fault::Invalid(_)) => Some(SIGILL),
+        }
+    }
+
+    fn resume(&mut self) -> io::Result<u8> {
+        // stepping off the breakpoint we're sitting on
+        if let Some(signal) = self.step() {
+            return Ok(signal);
+        }
+        let mut count = 0;
+        loop {
+            if self.breakpoints.contains(&self.machine.pc()) {
+                return Ok(SIGTRAP);
+            }
+            if let Some(signal) = self.step() {
+                return Ok(signal);
+            }
+            count += 1;
+            if count % POLL_EVERY == 0 && self.interrupted()? {
+                return Ok(SIGINT);
+            }
+        }
+    }
+
+    fn registers(&self) -> String {
+        self.machine
+            .regs
+            .iter()
+            .map(|&reg| encode_hex(&(reg as u32).to_le_bytes()))
+            .collect()
+    }
+
+    fn set_registers(&mut self, text: &str) -> Option<()> {
+        let width = text.len() / 16;
+        for reg in 0..16 {
+            self.machine.regs[reg] = parse_register(text.get(reg * width..(reg + 1) * width)?)?;
+        }
+        Some(())
+    }
+
+    fn read_memory(&self, address: u16, length: usize) -> String {
+        let bytes: Vec<u8> = (0..length)
+            .map(|n| self.machine.read_byte(address.wrapping_add(n as u16)))
+            .collect();
+        encode_hex(&bytes)
+    }
+
+    fn write_memory(&mut self, address: u16, data: &[u8]) {
+        for (n, &byte) in data.iter().enumerate() {
+            self.machine
+                .write_byte(address.wrapping_add(n as u16), byte);
+        }
+    }
+
+    // Z0/z0 and Z1/z1 both just mean "stop at this address"
+    fn breakpoint(&mut self, text: &str, insert: bool) -> Option<()> {
+        let mut fields = text.split(',');
+        let kind = fields.next()?;
+        let address = u32::from_str_radix(fields.next()?, 16).ok()? as u16;
+        if kind != "0" && kind != "1" {
+            return None;
+        }
+        match insert {
+            true => self.breakpoints.insert(address),
+            false => self.breakpoints.remove(&address),
+        };
+        Some(())
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<Reply> {
+        let ok = |done: Option<()>| match done {
+            Some(()) => "OK".to_owned(),
+            None => "E01".to_owned(),
+        };
+        let command = packet.get(..1).unwrap_or("");
+        let rest = packet.get(1..).unwrap_or("");
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => self.registers(),
+            "G" => ok(self.set_registers(rest)),
+            "p" => match usize::from_str_radix(rest, 16) {
+                Ok(reg) if reg < 16 => encode_hex(&(self.machine.regs[reg] as u32).to_le_bytes()),
+                _ => "E01".to_owned(),
+            },
+            "P" => ok((|| {
+                let (reg, value) = rest.split_once('=')?;
+                let reg = usize::from_str_radix(reg, 16)
+                    .ok()
+                    .filter(|&reg| reg < 16)?;
+                self.machine.regs[reg] = parse_register(value)?;
+                Some(())
+            })()),
+            "m" => match address_length(rest) {
+                Some((address, length)) => self.read_memory(address, length),
+                None => "E01".to_owned(),
+            },
+            "M" => ok((|| {
+                let (header, data) = rest.split_once(':')?;
+                let (address, _) = address_length(header)?;
+                self.write_memory(address, &decode_hex(data)?);
+                Some(())
+            })()),
+            "s" => {
+                let signal = self.step().unwrap_or(SIGTRAP);
+                format!("S{signal:02x}")
+            }
+            "c" => {
+                let signal = self.resume()?;
+                format!("S{signal:02x}")
+            }
+            "Z" => ok(self.breakpoint(rest, true)),
+            "z" => ok(self.breakpoint(rest, false)),
+            "k" => return Ok(Reply::Close),
+            "D" => {
+                self.send("OK")?;
+                return Ok(Reply::Close);
+            }
+            _ if packet.starts_with("qSupported") => "PacketSize=1000;QStartNoAckMode+".to_owned(),
+            _ if packet == "QStartNoAckMode" => {
+                // the OK still gets acked, nothing after it does
+                self.send("OK")?;
+                self.acks = false;
+                return Ok(Reply::Sent);
+            }
+            "q" if packet == "qAttached" => "1".to_owned(),
+            "H" => "OK".to_owned(), // only one thread
+            _ => String::new(),
+        };
+        Ok(Reply::Send(reply))
+    }
+
+    // answer packets until gdb detaches, kills us or hangs up
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    // ctrl-c while we were already stopped
+                    self.send(&format!("S{SIGINT:02x}"))?;
+                    continue;
+                }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match self.handle(&packet)? {
+                Reply::Send(reply) => self.send(&reply)?,
+                Reply::Sent => (),
+                Reply::Close => return Ok(()),
+            }
+        }
+    }
+}
+
+// --gdb: load the image, wait for gdb on localhost and serve it until it detaches
+pub fn serve(
+    bytes: &[u8],
+    base: u32,
+    port: u16,
+    peripherals: Vec<Box<dyn Peripheral>>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("waiting for gdb on localhost:{port}");
+    let (stream, peer) = listener.accept()?;
+    eprintln!("gdb connected from {peer}");
+
+    let mut machine = Machine::with_peripherals(peripherals);
+    machine.load_image(bytes, base);
+    machine.reset();
+    let mut session = Session::new(stream, machine);
+    session.run()?;
+    eprintln!(
+        "gdb detached at pc {:04x}",
+        session.machine.regs[PC as usize]
+    );
+    Ok(())
+}
@@ -1,105 +1,70 @@
-use bitvec::{prelude::*, slice::BitSlice};
-
 use core::fmt;
-use lazy_static::lazy_static;
-use std::{
-    collections::HashMap,
-    fmt::{write, Display},
-    ops::Add,
-};
 
 use crate::{flow::Address, pseudo::PsuedoOpcode};
-lazy_static! {
-    pub static ref JUMP_MAP: HashMap<u8, JmpOpcode> = {
-        use JmpOpcode::*;
-        let mut m = HashMap::new();
-        m.insert(0b000, JNE);
-        m.insert(0b001, JEQ);
-        m.insert(0b010, JLO);
-        m.insert(0b011, JHS);
-        m.insert(0b100, JN);
-        m.insert(0b101, JGE);
-        m.insert(0b110, JL);
-        m.insert(0b111, JMP);
-        m
-    };
-    pub static ref ONE_MAP: HashMap<u8, OneOpcode> = {
-        use OneOpcode::*;
-        let mut m = HashMap::new();
-        m.insert(0b000, RRC);
-        m.insert(0b001, SWPB);
-        m.insert(0b010, RRA);
-        m.insert(0b011, SXT);
-        m.insert(0b100, PUSH);
-        m.insert(0b101, CALL);
-        m.insert(0b110, RETI);
-        m
-    };
-    pub static ref TWO_MAP: HashMap<u8, TwoOpcode> = {
-        use TwoOpcode::*;
-        let mut m = HashMap::new();
-        m.insert(0b0100, MOV);
-        m.insert(0b0101, ADD);
-        m.insert(0b0110, ADDC);
-        m.insert(0b0111, SUBC);
-        m.insert(0b1000, SUB);
-        m.insert(0b1001, CMP);
-        m.insert(0b1010, DADD);
-        m.insert(0b1011, BIT);
-        m.insert(0b1100, BIC);
-        m.insert(0b1101, BIS);
-        m.insert(0b1110, XOR);
-        m.insert(0b1111, AND);
-        m
-    };
-    pub static ref ADDRESS_MODE_MAP: HashMap<u8, AddressMode> = {
-        use AddressMode::*;
-        let mut m = HashMap::new();
-        // 2 bit for src)
-        m.insert(0b00, Direct);
-        m.insert(0b01, Indexed);
-        m.insert(0b10, Indirect);
-        m.insert(0b11, IndirectIncrement);
-        m
-    };
-
-    pub static ref ADDRESS_MODE_SR_MAP: HashMap<u8, AddressMode> = {
-        use AddressMode::*;
-        let mut m = HashMap::new();
-        m.insert(0b00, Direct);
-        m.insert(0b01, AbsoluteAddressing);
-        m.insert(0b10, Const4);
-        m.insert(0b11, Const8);
-        m
-    };
-    pub static ref ADDRESS_MODE_ZERO_MAP: HashMap<u8, AddressMode> = {
-        use AddressMode::*;
-        let mut m = HashMap::new();
-
-        m.insert(0b00, Const0);
-        m.insert(0b01, Const1);
-        m.insert(0b10, Const2);
-        m.insert(0b11, ConstNeg1);
-        m
-    };
 
-}
+// decode tables, indexed straight by the opcode bits they're named after.
+// None is an encoding the plain MSP430 doesn't have
+pub const JUMPS: [JmpOpcode; 8] = {
+    use JmpOpcode::*;
+    [JNE, JEQ, JLO, JHS, JN, JGE, JL, JMP] // bits 10-12
+};
+pub const ONE_OPERAND: [Option<OneOpcode>; 8] = {
+    use OneOpcode::*;
+    // bits 7-9, 0b111 is unused
+    [
+        Some(RRC),
+        Some(SWPB),
+        Some(RRA),
+        Some(SXT),
+        Some(PUSH),
+        Some(CALL),
+        Some(RETI),
+        None,
+    ]
+};
+pub const TWO_OPERAND: [Option<TwoOpcode>; 16] = {
+    use TwoOpcode::*;
+    // bits 12-15, 0-3 are format II, jumps and MSP430X extension words
+    [
+        None,
+        None,
+        None,
+        None,
+        Some(MOV),
+        Some(ADD),
+        Some(ADDC),
+        Some(SUBC),
+        Some(SUB),
+        Some(CMP),
+        Some(DADD),
+        Some(BIT),
+        Some(BIC),
+        Some(BIS),
+        Some(XOR),
+        Some(AND),
+    ]
+};
+// As, or Ad for a destination, which only has the first two
+pub const ADDRESS_MODES: [AddressMode; 4] = {
+    use AddressMode::*;
+    [Direct, Indexed, Indirect, IndirectIncrement]
+};
+pub const ADDRESS_MODES_SR: [AddressMode; 4] = {
+    use AddressMode::*;
+    [Direct, AbsoluteAddressing, Const4, Const8]
+};
+pub const ADDRESS_MODES_ZERO: [AddressMode; 4] = {
+    use AddressMode::*;
+    [Const0, Const1, Const2, ConstNeg1]
+};
 
 pub const PC: u8 = 0; // Program Counter
 pub const SP: u8 = 1; // Stack Pointer
 pub const SR: u8 = 2; // Status Register
 pub const ZR: u8 = 3; // Zero Register
 
-pub struct CurrentBinaryScope<'w> {
-    pub used_words: UsedWords, // stores words that were used for this current instruction
-    pub address: Address,
-    pub index: usize,
-    pub current_word: Word,
-    pub next_word: Word,
-    pub vec: &'w Vec<Word>,
-}
-
-pub struct UsedWords(Vec<Word>);
+#[derive(Clone)]
+pub struct UsedWords(pub Vec<Word>);
 
 impl fmt::Display for UsedWords {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -110,45 +75,23 @@ impl fmt::Display for UsedWords {
             new += " ";
         }
 
-        write!(f, "{}", new.trim())
+        f.pad(new.trim())
     }
 }
 
-impl CurrentBinaryScope<'_> {
-    pub fn new(binary_vec: &Vec<Word>) -> CurrentBinaryScope {
-        CurrentBinaryScope {
-            used_words: UsedWords(Vec::new()),
-            address: Address(0),
-            index: 0,
-            current_word: Word(0),
-            next_word: Word(0),
-            vec: binary_vec,
-        }
-    }
-    pub fn step(&mut self) {
-        self.used_words.0.clear();
-        self.index += 1;
-        self.update();
-    }
-
-    pub fn get_next(&mut self) -> Word {
-        self.index += 1;
-        self.used_words.0.push(self.vec[self.index]);
-        self.vec[self.index]
-    }
-
-    pub fn update(&mut self) {
-        self.address = Address::from_index(self.index);
-        self.current_word = *self.vec.get(self.index).unwrap_or(&Word(0));
-        self.next_word = *self.vec.get(self.index + 1).unwrap_or(&Word(0));
-        self.used_words.0.push(self.vec[self.index]);
-    }
+// one decoded instruction of the listing
+pub struct Line {
+    pub address: Address,
+    pub words: UsedWords,
+    pub raw: Instruction, // straight out of the decoder, constant generator modes intact
+    pub instruction: Instruction,
+    pub targets: Vec<Address>, // where a computed branch can go, filled in after decoding
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct Word(pub u16);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum JmpOpcode {
     JNE,
     JEQ,
@@ -203,9 +146,6 @@ pub enum AddressMode {
     Const2,
     ConstNeg1,
 }
-#[derive(Clone, Copy, Debug)]
-pub struct Indexing(pub u8);
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DestReg(pub u8);
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -240,6 +180,7 @@ pub enum Instruction {
         src_index: Option<Word>,
         dest_index: Option<Word>,
     },
+    #[allow(non_camel_case_types)]
     TWO_BUT_WITH_A_SIGNED_WORD_I_HATE_RUST {
         opcode: TwoOpcode,
         src: SrcReg,
@@ -276,12 +217,7 @@ impl fmt::Display for Instruction {
                 condition, offset, ..
             } => {
                 let offset = offset.0;
-                let pos_or_neg;
-                if offset >= 0 {
-                    pos_or_neg = "+";
-                } else {
-                    pos_or_neg = "-";
-                }
+                let pos_or_neg = if offset >= 0 { "+" } else { "-" };
                 write!(f, "{condition:?}    ${pos_or_neg}{:#x}", offset.abs())
             }
             Instruction::ONE {
@@ -291,9 +227,15 @@ impl fmt::Display for Instruction {
                 dest,
                 dest_index,
             } => {
+                if opcode == &OneOpcode::RETI {
+                    return write!(f, "{opcode:?}");
+                }
                 if dam == &AddressMode::IndirectIncrement && dest.0 == PC {
                     return write!(f, "{opcode:?}   {b} #{:#x}", dest_index.unwrap().0);
                 }
+                if dam == &AddressMode::AbsoluteAddressing {
+                    return write!(f, "{opcode:?}{b}   &{:#x}", dest_index.unwrap().0);
+                }
 
                 let indirect = match dam {
                     Indirect | IndirectIncrement => "@",
@@ -329,22 +271,20 @@ impl fmt::Display for Instruction {
                 src_index,
                 dest_index,
             } => {
-                let indexing = match dam {
-                    Indexed => format!("({:#x})", dest_index.unwrap().0),
-                    _ => "".to_owned(),
-                };
+                let destination = format_destination(dam, dest, dest_index);
 
                 if sam == &AddressMode::IndirectIncrement && src.0 == PC {
                     return write!(
                         f,
-                        "{opcode:?}{b}    #{:#x}, {indexing}{dest}",
+                        "{opcode:?}{b}    #{:#x}, {destination}",
                         src_index.unwrap().0
                     );
                 } else if sam == &AddressMode::AbsoluteAddressing {
-                    return write!(f, "{opcode:?}{b}    &{:#x}, {dest}", src_index.unwrap().0);
-                } else if dam == &AddressMode::AbsoluteAddressing {
-                    //println!("welp");
-                    return write!(f, "{opcode:?}{b}    {src}, &{:#x}", dest_index.unwrap().0);
+                    return write!(
+                        f,
+                        "{opcode:?}{b}    &{:#x}, {destination}",
+                        src_index.unwrap().0
+                    );
                 }
                 let indirect = match sam {
                     Indirect | IndirectIncrement => "@",
@@ -362,7 +302,7 @@ impl fmt::Display for Instruction {
 
                 write!(
                     f,
-                    "{opcode:?}{b}    {indirect}{sindexing}{src}{increment}, {indexing}{dest}"
+                    "{opcode:?}{b}    {indirect}{sindexing}{src}{increment}, {destination}"
                 )
             }
             Instruction::PSEUDO {
@@ -395,13 +335,20 @@ impl fmt::Display for Instruction {
                 src_index,
                 dest_index,
             } => {
+                let destination = format_destination(dam, dest, dest_index);
+
                 if sam == &AddressMode::IndirectIncrement && src.0 == PC {
-                    return write!(f, "{opcode:?}{b}    #{:#x}, {dest}", src_index.unwrap().0);
+                    return write!(
+                        f,
+                        "{opcode:?}{b}    #{:#x}, {destination}",
+                        src_index.unwrap().0
+                    );
                 } else if sam == &AddressMode::AbsoluteAddressing {
-                    return write!(f, "{opcode:?}{b}    &{:#x}, {dest}", src_index.unwrap().0);
-                } else if dam == &AddressMode::AbsoluteAddressing {
-                    //println!("welp");
-                    return write!(f, "{opcode:?}{b}    {src}, &{:#x}", dest_index.unwrap().0);
+                    return write!(
+                        f,
+                        "{opcode:?}{b}    &{:#x}, {destination}",
+                        src_index.unwrap().0
+                    );
                 }
                 let indirect = match sam {
                     Indirect | IndirectIncrement => "@",
@@ -411,12 +358,24 @@ impl fmt::Display for Instruction {
                     IndirectIncrement => "+",
                     _ => "",
                 };
-                write!(f, "{opcode:?}{b}    {indirect}{src}{increment}, {dest}")
+                write!(
+                    f,
+                    "{opcode:?}{b}    {indirect}{src}{increment}, {destination}"
+                )
             }
         }
     }
 }
 
+// format I destinations are only ever Rn, x(Rn) or &addr
+fn format_destination(dam: &AddressMode, dest: &DestReg, dest_index: &Option<Word>) -> String {
+    match dam {
+        AddressMode::Indexed => format!("({:#x}){dest}", dest_index.unwrap().0),
+        AddressMode::AbsoluteAddressing => format!("&{:#x}", dest_index.unwrap().0),
+        _ => format!("{dest}"),
+    }
+}
+
 impl fmt::Display for Bbit {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -472,28 +431,33 @@ pub fn check_special_am(instruction: &Instruction) -> Instruction {
             let mut source_index = src_index;
             let mut source_am = sam;
 
-            match sam {
-                &AddressMode::Const0 => {
+            match *sam {
+                AddressMode::Const0 => {
                     source_am = &AddressMode::IndirectIncrement;
                     source = &SrcReg(PC);
                     source_index = &Some(Word(0));
                 }
-                &AddressMode::Const1 => {
+                AddressMode::Const1 => {
                     source_am = &AddressMode::IndirectIncrement;
                     source = &SrcReg(PC);
                     source_index = &Some(Word(1));
                 }
-                &AddressMode::Const4 => {
+                AddressMode::Const2 => {
+                    source_am = &AddressMode::IndirectIncrement;
+                    source = &SrcReg(PC);
+                    source_index = &Some(Word(2));
+                }
+                AddressMode::Const4 => {
                     source_am = &AddressMode::IndirectIncrement;
                     source = &SrcReg(PC);
                     source_index = &Some(Word(4));
                 }
-                &AddressMode::Const8 => {
+                AddressMode::Const8 => {
                     source_am = &AddressMode::IndirectIncrement;
                     source = &SrcReg(PC);
                     source_index = &Some(Word(8));
                 }
-                &AddressMode::ConstNeg1 => {
+                AddressMode::ConstNeg1 => {
                     source_am = &AddressMode::IndirectIncrement;
                     source = &SrcReg(PC);
                     let source_index = &Some(SignedWord(-1));
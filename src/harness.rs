@@ -0,0 +1,190 @@
+use crate::{
+    analyze,
+    annotations::{sidecar, Annotations},
+    emulator::{Fault, Machine, Step},
+    functions::{find_functions_from, Function},
+    globals::{Line, SP},
+    load_binary,
+    memory::VECTOR_TABLE,
+};
+
+/*
+    Calling functions in a firmware image from a #[test]:
+
+        let mut firmware = Harness::load("build/app.bin", 0xc000);
+        firmware.write_bytes(0x0200, b"\x01\x02\x03");
+        let sum = firmware.call_named("sub_c0de", &[0x0200, 3]).unwrap();
+        assert_eq!(sum.value(), 6);
+
+    The base is where the image sits, the memory map's image_base for
+    the part. Names are the ones the listing gives functions, labels from the
+    annotations file included. Arguments go in
+    registers per the ABI, four at most; anything that needs the stack for
+    arguments or returns a struct has to be set up by hand through
+    `machine`. The call returns when the function RETs to the sentinel
+    return address pushed before it, with the stack back where it started.
+
+    There are no peripherals unless you add them to machine.peripherals,
+    so nothing resets halfway through a long checksum.
+*/
+
+pub const RETURN_ADDRESS: u16 = 0x0000; // nothing runs out of the SFRs
+pub const STACK_TOP: u16 = 0x0400; // end of the G2553's 512 bytes of RAM
+pub const MAX_CYCLES: u64 = 10_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Abi {
+    Eabi,   // msp430-elf-gcc, arguments in r12, r13, r14, r15
+    Legacy, // mspgcc, arguments in r15, r14, r13, r12
+}
+
+impl Abi {
+    pub fn arguments(self) -> [usize; 4] {
+        match self {
+            Abi::Eabi => [12, 13, 14, 15],
+            Abi::Legacy => [15, 14, 13, 12],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CallError {
+    UnknownFunction(String),
+    TooManyArguments(usize),
+    Fault(u16),   // PC of an instruction that can't execute
+    Reset,        // a peripheral reset the chip
+    Timeout(u64), // still running after this many cycles
+}
+
+// the registers a call left behind
+#[derive(Debug)]
+pub struct Return {
+    pub regs: [u16; 16],
+    pub cycles: u64, // just this call
+    pub abi: Abi,
+}
+
+impl Return {
+    pub fn value(&self) -> u16 {
+        self.regs[self.abi.arguments()[0]]
+    }
+    // 32 bit results, low word in the lower numbered register of the pair
+    pub fn value32(&self) -> u32 {
+        let (low, high) = match self.abi {
+            Abi::Eabi => (12, 13),
+            Abi::Legacy => (14, 15),
+        };
+        self.regs[low] as u32 | (self.regs[high] as u32) << 16
+    }
+}
+
+pub struct Harness {
+    pub machine: Machine,
+    pub abi: Abi,
+    pub stack_top: u16,
+    pub max_cycles: u64,
+    lines: Vec<Line>,
+    functions: Vec<Function>,
+}
+
+impl Harness {
+    pub fn new(bytes: &[u8], base: u32) -> Harness {
+        Harness::annotated(bytes, base, &Annotations::default())
+    }
+
+    // with the names from an annotations file
+    pub fn annotated(bytes: &[u8], base: u32, notes: &Annotations) -> Harness {
+        let lines = analyze(bytes, base);
+        let starts = notes.function_starts();
+        let mut functions = find_functions_from(&lines, bytes, base, &starts, Some(VECTOR_TABLE));
+        notes.apply(&lines, &mut functions);
+        let mut machine = Machine::new();
+        machine.load_image(bytes, base);
+        machine.reset();
+        Harness {
+            machine,
+            abi: Abi::Eabi,
+            stack_top: STACK_TOP,
+            max_cycles: MAX_CYCLES,
+            lines,
+            functions,
+        }
+    }
+
+    // picks up <path>.notes.toml if there is one
+    pub fn load(path: &str, base: u32) -> Harness {
+        let notes = Annotations::load(&sidecar(path)).unwrap_or_else(|e| panic!("{e}"));
+        Harness::annotated(&load_binary(path), base, &notes)
+    }
+
+    // None for functions above 64K too, the emulator can't get there
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        let function = self
+            .functions
+            .iter()
+            .find(|f| f.name(&self.lines) == name)?;
+        u16::try_from(self.lines[function.start].address.0).ok()
+    }
+
+    pub fn call_named(&mut self, name: &str, args: &[u16]) -> Result<Return, CallError> {
+        match self.address_of(name) {
+            Some(address) => self.call(address, args),
+            None => Err(CallError::UnknownFunction(name.to_owned())),
+        }
+    }
+
+    // memory and the registers the callee doesn't touch carry over from the last call
+    pub fn call(&mut self, address: u16, args: &[u16]) -> Result<Return, CallError> {
+        let registers = self.abi.arguments();
+        if args.len() > registers.len() {
+            return Err(CallError::TooManyArguments(args.len()));
+        }
+        for (&reg, &arg) in registers.iter().zip(args) {
+            self.machine.regs[reg] = arg;
+        }
+        self.machine.regs[SP as usize] = self.stack_top;
+        self.machine.push(RETURN_ADDRESS);
+        self.machine.regs[0] = address;
+
+        let start = self.machine.cycles;
+        while self.machine.pc() != RETURN_ADDRESS
+            || self.machine.regs[SP as usize] != self.stack_top
+        {
+            let spent = self.machine.cycles - start;
+            if spent > self.max_cycles {
+                return Err(CallError::Timeout(spent));
+            }
+            match self.machine.step() {
+                Ok(Step::Ran | Step::Interrupt | Step::Sleeping) => (),
+                Ok(Step::Reset) => return Err(CallError::Reset),
+                Err(Fault::Invalid(pc)) => return Err(CallError::Fault(pc)),
+            }
+        }
+        Ok(Return {
+            regs: self.machine.regs,
+            cycles: self.machine.cycles - start,
+            abi: self.abi,
+        })
+    }
+
+    pub fn read_bytes(&self, address: u16, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|n| self.machine.read_byte(address.wrapping_add(n as u16)))
+            .collect()
+    }
+
+    pub fn write_bytes(&mut self, address: u16, bytes: &[u8]) {
+        for (n, &byte) in bytes.iter().enumerate() {
+            self.machine
+                .write_byte(address.wrapping_add(n as u16), byte);
+        }
+    }
+
+    pub fn read_word(&self, address: u16) -> u16 {
+        self.machine.read_word(address)
+    }
+
+    pub fn write_word(&mut self, address: u16, value: u16) {
+        self.machine.write_word(address, value);
+    }
+}
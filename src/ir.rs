@@ -0,0 +1,396 @@
+use crate::globals::{AddressMode, Instruction, JmpOpcode, Line, OneOpcode, TwoOpcode, Word, PC};
+
+/*
+    One lifted instruction says everything the CPU does for it: which
+    operands it reads and writes, at what width, and how every flag comes
+    out. Lifting works on Line::raw, emulated instructions are just aliases
+    for these, and the constant generator has already turned into #n.
+
+    Symbolic operands (x(PC)) are lifted to absolute ones, the address of
+    the extension word is known by then.
+*/
+
+// SR bits
+pub const C: u16 = 1 << 0;
+pub const Z: u16 = 1 << 1;
+pub const N: u16 = 1 << 2;
+pub const GIE: u16 = 1 << 3;
+pub const CPUOFF: u16 = 1 << 4;
+pub const V: u16 = 1 << 8;
+
+// the flags an instruction can set, a set of C Z N V (not the SR bit positions)
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Flags(pub u8);
+
+impl Flags {
+    pub const C: Flags = Flags(1);
+    pub const Z: Flags = Flags(2);
+    pub const N: Flags = Flags(4);
+    pub const V: Flags = Flags(8);
+    pub const ALL: Flags = Flags(15);
+
+    pub fn union(self, other: Flags) -> Flags {
+        Flags(self.0 | other.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+impl Width {
+    pub fn mask(self) -> u16 {
+        match self {
+            Width::Byte => 0xff,
+            Width::Word => 0xffff,
+        }
+    }
+    pub fn sign(self) -> u16 {
+        match self {
+            Width::Byte => 0x80,
+            Width::Word => 0x8000,
+        }
+    }
+    pub fn bytes(self) -> u16 {
+        match self {
+            Width::Byte => 1,
+            Width::Word => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Reg(u8),
+    Imm(u16),
+    Absolute(u16),
+    Indexed(u8, u16),      // base register + offset
+    Indirect(u8),          // @Rn
+    IndirectIncrement(u8), // @Rn+, the register moves on by the operand width (2 for SP)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AluOp {
+    Mov,
+    Add,
+    Addc,
+    Sub,
+    Subc,
+    Cmp,
+    Dadd,
+    Bit,
+    Bic,
+    Bis,
+    Xor,
+    And,
+    Rrc,
+    Rra,
+    Swpb,
+    Sxt,
+}
+
+impl AluOp {
+    // CMP and BIT only set flags
+    pub fn writes_result(self) -> bool {
+        !matches!(self, AluOp::Cmp | AluOp::Bit)
+    }
+    // MOV doesn't care what the destination held
+    pub fn reads_destination(self) -> bool {
+        !matches!(self, AluOp::Mov)
+    }
+    pub fn reads_carry(self) -> bool {
+        matches!(self, AluOp::Addc | AluOp::Subc | AluOp::Dadd | AluOp::Rrc)
+    }
+    pub fn flags_written(self) -> Flags {
+        match self {
+            AluOp::Mov | AluOp::Bic | AluOp::Bis | AluOp::Swpb => Flags::default(),
+            AluOp::Dadd => Flags::C.union(Flags::Z).union(Flags::N), // V is undefined
+            _ => Flags::ALL,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ir {
+    // single operand instructions have the same operand as src and dst
+    Alu {
+        op: AluOp,
+        width: Width,
+        src: Operand,
+        dst: Operand,
+    },
+    Push {
+        width: Width,
+        src: Operand,
+    },
+    Call {
+        target: Operand, // the value of the operand is where it goes, so CALL #x is Imm(x)
+    },
+    Reti,
+    Jump {
+        condition: JmpOpcode,
+        target: u16, // CPU address
+    },
+    Invalid,
+}
+
+pub fn alu_op(opcode: TwoOpcode) -> AluOp {
+    match opcode {
+        TwoOpcode::MOV => AluOp::Mov,
+        TwoOpcode::ADD => AluOp::Add,
+        TwoOpcode::ADDC => AluOp::Addc,
+        TwoOpcode::SUBC => AluOp::Subc,
+        TwoOpcode::SUB => AluOp::Sub,
+        TwoOpcode::CMP => AluOp::Cmp,
+        TwoOpcode::DADD => AluOp::Dadd,
+        TwoOpcode::BIT => AluOp::Bit,
+        TwoOpcode::BIC => AluOp::Bic,
+        TwoOpcode::BIS => AluOp::Bis,
+        TwoOpcode::XOR => AluOp::Xor,
+        TwoOpcode::AND => AluOp::And,
+    }
+}
+
+fn width(b: bool) -> Width {
+    match b {
+        true => Width::Byte,
+        false => Width::Word,
+    }
+}
+
+// `ext` is the CPU address of the extension word this operand would use
+fn operand(mode: AddressMode, reg: u8, index: Option<Word>, ext: u16) -> Operand {
+    let index = index.map_or(0, |word| word.0);
+    match mode {
+        AddressMode::Direct => Operand::Reg(reg),
+        AddressMode::Indexed if reg == PC => Operand::Absolute(ext.wrapping_add(index)),
+        AddressMode::Indexed => Operand::Indexed(reg, index),
+        AddressMode::Indirect => Operand::Indirect(reg),
+        AddressMode::IndirectIncrement if reg == PC => Operand::Imm(index),
+        AddressMode::IndirectIncrement => Operand::IndirectIncrement(reg),
+        AddressMode::AbsoluteAddressing => Operand::Absolute(index),
+        AddressMode::Const4 => Operand::Imm(4),
+        AddressMode::Const8 => Operand::Imm(8),
+        AddressMode::Const0 => Operand::Imm(0),
+        AddressMode::Const1 => Operand::Imm(1),
+        AddressMode::Const2 => Operand::Imm(2),
+        AddressMode::ConstNeg1 => Operand::Imm(0xffff),
+    }
+}
+
+fn uses_extension_word(mode: AddressMode, reg: u8) -> bool {
+    match mode {
+        AddressMode::Indexed | AddressMode::AbsoluteAddressing => true,
+        AddressMode::IndirectIncrement => reg == PC,
+        _ => false,
+    }
+}
+
+pub fn lift(line: &Line) -> Ir {
+    lift_at(line, line.address.0 as u16) // the lifted PC is 16 bits, like the emulator
+}
+
+// for lines that weren't decoded from the image, the emulator runs code out of RAM too
+pub fn lift_at(line: &Line, address: u16) -> Ir {
+    let after_opcode = address.wrapping_add(2);
+    match line.raw {
+        Instruction::Invalid => Ir::Invalid,
+        Instruction::JMP { condition, offset } => Ir::Jump {
+            condition,
+            target: address.wrapping_add_signed(offset.0),
+        },
+        Instruction::ONE {
+            opcode,
+            b,
+            dam,
+            dest,
+            dest_index,
+        } => {
+            let target = operand(dam, dest.0, dest_index, after_opcode);
+            let op = match opcode {
+                OneOpcode::RRC => AluOp::Rrc,
+                OneOpcode::RRA => AluOp::Rra,
+                OneOpcode::SWPB => AluOp::Swpb,
+                OneOpcode::SXT => AluOp::Sxt,
+                OneOpcode::PUSH => {
+                    return Ir::Push {
+                        width: width(b.0),
+                        src: target,
+                    }
+                }
+                OneOpcode::CALL => return Ir::Call { target },
+                OneOpcode::RETI => return Ir::Reti,
+            };
+            let width = match op {
+                AluOp::Swpb | AluOp::Sxt => Width::Word, // no byte forms
+                _ => width(b.0),
+            };
+            Ir::Alu {
+                op,
+                width,
+                src: target,
+                dst: target,
+            }
+        }
+        Instruction::TWO {
+            opcode,
+            src,
+            dam,
+            b,
+            sam,
+            dest,
+            src_index,
+            dest_index,
+        } => {
+            let dest_ext = after_opcode.wrapping_add(2 * uses_extension_word(sam, src.0) as u16);
+            let op = alu_op(opcode);
+            Ir::Alu {
+                op,
+                width: width(b.0),
+                src: operand(sam, src.0, src_index, after_opcode),
+                dst: operand(dam, dest.0, dest_index, dest_ext),
+            }
+        }
+        // the decoder never puts these in raw
+        Instruction::TWO_BUT_WITH_A_SIGNED_WORD_I_HATE_RUST { .. } | Instruction::PSEUDO { .. } => {
+            Ir::Invalid
+        }
+    }
+}
+
+// what an ALU operation produces, flags it leaves alone are None
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AluResult {
+    pub value: u16,
+    pub c: Option<bool>,
+    pub z: Option<bool>,
+    pub n: Option<bool>,
+    pub v: Option<bool>,
+}
+
+impl AluResult {
+    // the new SR given the old one
+    pub fn apply(&self, sr: u16) -> u16 {
+        let mut sr = sr;
+        for (flag, bit) in [(self.c, C), (self.z, Z), (self.n, N), (self.v, V)] {
+            match flag {
+                Some(true) => sr |= bit,
+                Some(false) => sr &= !bit,
+                None => (),
+            }
+        }
+        sr
+    }
+}
+
+// binary coded decimal addition, a digit at a time
+fn bcd_add(src: u16, dst: u16, carry: bool, width: Width) -> (u16, bool) {
+    let digits = match width {
+        Width::Byte => 2,
+        Width::Word => 4,
+    };
+    let mut result = 0;
+    let mut carry = carry as u16;
+    for digit in 0..digits {
+        let shift = digit * 4;
+        let mut sum = (src >> shift & 0xf) + (dst >> shift & 0xf) + carry;
+        carry = (sum > 9) as u16;
+        if carry == 1 {
+            sum -= 10;
+        }
+        result |= (sum & 0xf) << shift;
+    }
+    (result, carry == 1)
+}
+
+// the result and flags of one operation, src is ignored by the single operand ones
+pub fn alu(op: AluOp, width: Width, src: u16, dst: u16, carry: bool) -> AluResult {
+    let mask = width.mask();
+    let sign = width.sign();
+    let (src, dst) = (src & mask, dst & mask);
+    let negative = |value: u16| value & sign != 0;
+
+    // ADD, ADDC, SUB, SUBC and CMP are all an add, the subtracts with the source inverted
+    let add = |a: u16, b: u16, carry_in: u16| {
+        let sum = a as u32 + b as u32 + carry_in as u32;
+        let value = sum as u16 & mask;
+        let overflow = negative(a) == negative(b) && negative(value) != negative(a);
+        AluResult {
+            value,
+            c: Some(sum > mask as u32),
+            z: Some(value == 0),
+            n: Some(negative(value)),
+            v: Some(overflow),
+        }
+    };
+    let logic = |value: u16, overflow: bool| AluResult {
+        value,
+        c: Some(value != 0),
+        z: Some(value == 0),
+        n: Some(negative(value)),
+        v: Some(overflow),
+    };
+    let untouched = |value: u16| AluResult {
+        value,
+        c: None,
+        z: None,
+        n: None,
+        v: None,
+    };
+
+    match op {
+        AluOp::Mov => untouched(src),
+        AluOp::Add => add(dst, src, 0),
+        AluOp::Addc => add(dst, src, carry as u16),
+        AluOp::Sub | AluOp::Cmp => add(dst, !src & mask, 1),
+        AluOp::Subc => add(dst, !src & mask, carry as u16),
+        AluOp::Dadd => {
+            let (value, carry) = bcd_add(src, dst, carry, width);
+            AluResult {
+                value,
+                c: Some(carry),
+                z: Some(value == 0),
+                n: Some(negative(value)),
+                v: None,
+            }
+        }
+        AluOp::Bit | AluOp::And => logic(dst & src, false),
+        AluOp::Xor => logic(dst ^ src, negative(src) && negative(dst)),
+        AluOp::Bic => untouched(dst & !src),
+        AluOp::Bis => untouched(dst | src),
+        AluOp::Rrc | AluOp::Rra => {
+            let top = match op {
+                AluOp::Rrc if carry => sign,
+                AluOp::Rra => dst & sign,
+                _ => 0,
+            };
+            let value = top | dst >> 1;
+            AluResult {
+                value,
+                c: Some(dst & 1 != 0),
+                z: Some(value == 0),
+                n: Some(negative(value)),
+                v: Some(false),
+            }
+        }
+        AluOp::Swpb => untouched(dst.swap_bytes()),
+        AluOp::Sxt => logic(dst as u8 as i8 as i16 as u16, false),
+    }
+}
+
+// whether a conditional jump is taken with the flags in sr
+pub fn condition_holds(condition: JmpOpcode, sr: u16) -> bool {
+    let flag = |bit: u16| sr & bit != 0;
+    match condition {
+        JmpOpcode::JNE => !flag(Z),
+        JmpOpcode::JEQ => flag(Z),
+        JmpOpcode::JLO => !flag(C),
+        JmpOpcode::JHS => flag(C),
+        JmpOpcode::JN => flag(N),
+        JmpOpcode::JGE => flag(N) == flag(V),
+        JmpOpcode::JL => flag(N) != flag(V),
+        JmpOpcode::JMP => true,
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    flow::{ends_block, is_conditional, Address},
+    globals::{Instruction, JmpOpcode, Line, PC},
+    ir::{lift, AluOp, Ir, Operand},
+};
+
+/*
+    msp430-gcc turns a switch into one of
+        CMP #n, r15 / JHS default / RLA r15 / MOV tbl(r15), PC      table of addresses
+        CMP #n, r15 / JHS default / RLA r15 / ADD #tbl, r15 / BR @r15
+        CMP #n, r15 / JHS default / RLA r15 / ADD r15, PC           table of JMPs right after
+    Without the bound check there's no telling how long the table is, so
+    those branches stay unresolved. The JHS has to be there too, a CMP
+    nothing branches on doesn't bound anything, and so does exactly one
+    RLA, the entries are words (or two byte JMPs).
+
+    Every entry has to land on an instruction the sweep decoded, and for
+    the inline form has to be a JMP, otherwise it wasn't a table.
+*/
+
+const LOOKBACK: usize = 8; // how far before the branch the bound check can be
+
+enum Table {
+    Inline,     // ADD rN, PC, the entries are the instructions that follow
+    Words(u16), // MOV tbl(rN), PC
+    PointedTo,  // MOV @rN, PC, the table address was added to rN
+}
+
+fn writes(ir: Ir, reg: u8) -> bool {
+    matches!(ir, Ir::Alu { op, dst: Operand::Reg(dest), .. } if dest == reg && op.writes_result())
+}
+
+// the word at a CPU address, None outside the image or at an odd address
+pub fn read_word(bytes: &[u8], base: u32, address: u32) -> Option<u16> {
+    let offset = address.checked_sub(base)? as usize;
+    let pair = bytes.get(offset..offset + 2)?;
+    offset
+        .is_multiple_of(2)
+        .then(|| u16::from_le_bytes([pair[0], pair[1]]))
+}
+
+fn jump_table(lines: &[Line], index: usize, bytes: &[u8], base: u32) -> Option<Vec<Address>> {
+    let (reg, kind) = match lift(&lines[index]) {
+        Ir::Alu {
+            op: AluOp::Add,
+            src: Operand::Reg(src),
+            dst: Operand::Reg(PC),
+            ..
+        } if src != PC => (src, Table::Inline),
+        Ir::Alu {
+            op: AluOp::Mov,
+            src: Operand::Indexed(src, tbl),
+            dst: Operand::Reg(PC),
+            ..
+        } => (src, Table::Words(tbl)),
+        Ir::Alu {
+            op: AluOp::Mov,
+            src: Operand::Indirect(src) | Operand::IndirectIncrement(src),
+            dst: Operand::Reg(PC),
+            ..
+        } if src != PC => (src, Table::PointedTo),
+        _ => return None,
+    };
+
+    // walk back through the block for the scaling, the table address and the bound
+    let mut scale = 1u32;
+    let mut table = None;
+    let mut count = None;
+    let mut bounded = false; // seen the JHS to the default case
+    for j in (index.saturating_sub(LOOKBACK)..index).rev() {
+        let instruction = &lines[j].instruction;
+        if ends_block(instruction) && !is_conditional(instruction) {
+            break;
+        }
+        match lift(&lines[j]) {
+            Ir::Alu {
+                op: AluOp::Cmp,
+                src: Operand::Imm(n),
+                dst: Operand::Reg(dest),
+                ..
+            } if dest == reg => {
+                count = bounded.then_some(n);
+                break;
+            }
+            Ir::Jump {
+                condition: JmpOpcode::JHS,
+                ..
+            } => bounded = true,
+            Ir::Alu {
+                op: AluOp::Add,
+                src: Operand::Imm(tbl),
+                dst: Operand::Reg(dest),
+                ..
+            } if dest == reg && table.is_none() => table = Some(tbl),
+            // RLA rN is ADD rN, rN
+            Ir::Alu {
+                op: AluOp::Add,
+                src: Operand::Reg(src),
+                dst: Operand::Reg(dest),
+                ..
+            } if src == reg && dest == reg => scale *= 2,
+            ir if writes(ir, reg) => return None,
+            _ => (),
+        }
+    }
+    let count = count? as u32;
+    if scale != 2 {
+        return None;
+    }
+
+    let line = &lines[index];
+    let inline = matches!(kind, Table::Inline);
+    let targets: Vec<Address> = match kind {
+        Table::Inline => {
+            let after = line.address.0 + line.words.0.len() as u32 * 2;
+            (0..count).map(|k| Address(after + k * scale)).collect()
+        }
+        Table::Words(tbl) => entries(bytes, base, tbl.into(), count, scale)?,
+        Table::PointedTo => entries(bytes, base, table?.into(), count, scale)?,
+    };
+    let valid = targets.iter().all(|&target| {
+        match lines.binary_search_by_key(&target, |line| line.address) {
+            Ok(i) => !inline || matches!(lines[i].instruction, Instruction::JMP { .. }),
+            Err(_) => false, // mid instruction, or outside the image
+        }
+    });
+    valid.then_some(targets)
+}
+
+fn entries(bytes: &[u8], base: u32, table: u32, count: u32, scale: u32) -> Option<Vec<Address>> {
+    (0..count)
+        .map(|k| {
+            let target = read_word(bytes, base, table + k * scale)?;
+            Address::code(target.into())
+        })
+        .collect()
+}
+
+pub fn resolve_jump_tables(lines: &mut [Line], bytes: &[u8], base: u32) {
+    for index in 0..lines.len() {
+        if let Some(targets) = jump_table(lines, index, bytes, base) {
+            lines[index].targets = targets;
+        }
+    }
+}
@@ -0,0 +1,1403 @@
+pub mod annotations;
+pub mod globals;
+use globals::*;
+pub mod cycles;
+pub mod data;
+pub mod diff;
+pub mod flow;
+pub mod functions;
+pub mod jumptables;
+pub mod lint;
+pub mod listing;
+pub mod options;
+pub mod pseudo;
+pub mod stack;
+pub mod stats;
+use jumptables::*;
+pub mod constants;
+use constants::*;
+pub mod decoder;
+use decoder::*;
+pub mod decompile;
+pub mod device;
+pub mod emulator;
+pub mod gdb;
+pub mod harness;
+pub mod ir;
+pub mod liveness;
+pub mod memory;
+pub mod peripherals;
+pub mod profile;
+pub mod trace;
+pub mod tui;
+
+// the image exactly as it is in the file, everything else borrows it
+pub fn load_binary(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap()
+}
+
+// everything that can be worked out about single lines before looking at functions,
+// with the image placed at base (the memory map's image_base)
+pub fn analyze(bytes: &[u8], base: u32) -> Vec<Line> {
+    let mut lines = disassemble(bytes, base);
+    resolve_jump_tables(&mut lines, bytes, base);
+    propagate_constants(&mut lines, bytes, base);
+    lines
+}
+
+// linear sweep over the whole image
+pub fn disassemble(bytes: &[u8], base: u32) -> Vec<Line> {
+    Cursor::new(bytes, base).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decompile::decompile, emulator::*, flow::*, functions::find_functions};
+
+    // extension words that don't collide with any constant generator value
+    const EXT_SRC: u16 = 0x1234;
+    const EXT_DEST: u16 = 0x5678;
+
+    // where the test images go, the start of the default device's flash
+    const BASE: u32 = 0x8000;
+
+    // 32 KB of flash at 0x8000, code from the start and (vector, address) pairs at the end
+    fn flash(code: &[u16], vectors: &[(usize, u16)]) -> Vec<u8> {
+        let mut bytes = vec![0; 0x8000];
+        for (n, word) in code.iter().enumerate() {
+            bytes[n * 2..n * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        }
+        for &(vector, address) in vectors {
+            let at = 0x8000 - 32 + vector * 2;
+            bytes[at..at + 2].copy_from_slice(&address.to_le_bytes());
+        }
+        bytes
+    }
+
+    // number of words the MSP430 family user's guide says an opcode occupies
+    fn expected_len(op: u16) -> usize {
+        let reg_src = ((op >> 8) & 0xf) as u8;
+        let reg_dest = (op & 0xf) as u8;
+        let am = (op >> 4) & 0b11;
+        let ext = |reg: u8| match am {
+            0b01 => reg != ZR,
+            0b11 => reg == PC,
+            _ => false,
+        };
+        match op >> 12 {
+            0x0 | 0x1 if op & 0xfc00 != 0x1000 => 1, // unhandled MSP430X opcodes
+            0x1 if (op >> 7) & 0b111 == 0b111 => 1,  // unused format II opcode
+            0x1 if (op >> 7) & 0b111 == 0b110 => 1,  // RETI ignores its operand bits
+            0x1 => 1 + ext(reg_dest) as usize,
+            0x2 | 0x3 => 1,
+            _ => 1 + ext(reg_src) as usize + ((op >> 7) & 1) as usize,
+        }
+    }
+
+    #[test]
+    fn every_opcode_decodes() {
+        for op in 0..=u16::MAX {
+            let bytes = [op, EXT_SRC, EXT_DEST].map(u16::to_le_bytes).concat();
+            let decoded = decode(&bytes, 0).unwrap();
+            let instruction = decoded.instruction;
+
+            // formatting unwraps every index the addressing modes say is there
+            let text = format!("{instruction}");
+
+            assert_eq!(
+                decoded.length / 2,
+                expected_len(op),
+                "{op:#06x} decoded as `{text}`"
+            );
+            if let Instruction::Invalid = instruction {
+                assert_eq!(expected_len(op), 1, "{op:#06x}");
+            }
+        }
+    }
+
+    #[test]
+    fn extension_words_land_in_the_right_operand() {
+        // MOV &0x1234, &0x5678
+        let bytes = [0x4292, EXT_SRC, EXT_DEST].map(u16::to_le_bytes).concat();
+        let instruction = decode(&bytes, 0).unwrap().raw;
+        assert_eq!(format!("{instruction}"), "MOV    &0x1234, &0x5678");
+        // PUSH 0x1234(r5), one operand indexes used to come out byte swapped
+        let bytes = [0x1215, EXT_SRC].map(u16::to_le_bytes).concat();
+        let instruction = decode(&bytes, 0).unwrap().raw;
+        assert!(matches!(
+            instruction,
+            Instruction::ONE {
+                dest_index: Some(Word(EXT_SRC)),
+                ..
+            }
+        ));
+        // any offset decodes on its own, and backwards finds what ends there
+        let bytes = [0x4303, 0x4292, EXT_SRC, EXT_DEST, 0x4303]
+            .map(u16::to_le_bytes)
+            .concat();
+        assert_eq!(decode(&bytes, 2).unwrap().length, 6);
+        assert!(decode(&bytes, 3).is_none());
+        // 0x5678 on its own is an ADD too, so the last extension word is also a candidate
+        assert_eq!(decoder::starts_before(&bytes, 8), [6, 2]);
+    }
+
+    #[test]
+    fn a_full_image_lifts_right_up_to_the_reset_vector() {
+        use crate::ir::{lift, Ir};
+        // NOP filler, the vector at 0xfffc decodes as SUB 0x8000(PC), SR with its word at 0xfffe
+        let mut code = vec![0x4303; 0x4000];
+        code[0x3ffe] = 0x8012;
+        code[0x3fff] = 0x8000;
+        let bytes = flash(&code, &[]);
+        let lines = analyze(&bytes, BASE);
+        assert_eq!(lines.last().unwrap().address, Address(0xfffc));
+        for line in &lines {
+            assert_ne!(lift(line), Ir::Invalid);
+        }
+    }
+
+    #[test]
+    fn registers_die_at_their_last_read() {
+        use crate::{
+            ir::Flags,
+            liveness::{def_use, free_registers, liveness, RegSet},
+        };
+        // MOV #1, r12 / ADD r13, r12 / MOV r12, r15 / RET
+        let bytes = [0x431c, 0x5d0c, 0x4c0f, 0x4130]
+            .map(u16::to_le_bytes)
+            .concat();
+        let lines = analyze(&bytes, BASE);
+        let live = liveness(&lines);
+        assert!(live[0].contains(13));
+        assert!(!live[0].contains(12));
+        assert!(live[1].contains(12));
+        // r12 is a return value as far as RET knows, r15 gets overwritten first
+        assert!(!live[2].contains(15));
+        assert_eq!(
+            live[3].without(RegSet::of(&[1])),
+            RegSet::of(&[4, 5, 6, 7, 8, 9, 10, 12, 13, 14, 15])
+        );
+        // somewhere to put a hook
+        assert!(free_registers(&live, 2).contains(15));
+        assert_eq!(free_registers(&live, 3), RegSet::of(&[11]));
+
+        // PUSH r12 / MOV #1, r12 / POP r12 / RETI, the interrupted code wants all of them back
+        let bytes = [0x120c, 0x431c, 0x413c, 0x1300]
+            .map(u16::to_le_bytes)
+            .concat();
+        let lines = analyze(&bytes, BASE);
+        let live = liveness(&lines);
+        assert_eq!(free_registers(&live, 0), RegSet::default());
+        assert_eq!(free_registers(&live, 3), RegSet::default());
+        // between the save and the restore r12 is fair game
+        assert_eq!(free_registers(&live, 1), RegSet::of(&[12]));
+
+        // CLRC / RLC r12 / RET, RLC is ADDC r12, r12 and needs the carry CLRC left
+        let bytes = [0xc312, 0x6c0c, 0x4130].map(u16::to_le_bytes).concat();
+        let lines = analyze(&bytes, BASE);
+        let (clrc, rlc) = (def_use(&lines[0]), def_use(&lines[1]));
+        assert_eq!((clrc.flags_written, rlc.flags_read), (Flags::C, Flags::C));
+        assert!(liveness(&lines)[1].contains(SR));
+        assert!(matches!(
+            lines[1].instruction,
+            Instruction::PSEUDO {
+                opcode: pseudo::PsuedoOpcode::RLC,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn forward_branch_becomes_an_if() {
+        // CMP #5, r12 / JNE $+4 / MOV #1, r12 / RET
+        let bytes = [0x903c, 5, 0x2001, 0x431c, 0x4130]
+            .map(u16::to_le_bytes)
+            .concat();
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let text = decompile(&lines, &functions, &[]);
+        assert!(text.contains("uint16_t sub_8000(uint16_t r12)"), "{text}");
+        assert!(
+            text.contains("    if (r12 == 0x5) {\n        r12 = 0x1;\n    }\n    return r12;"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn alu_flags_match_the_users_guide() {
+        use crate::ir::{alu, AluOp, Width, C, N, V, Z};
+        let sr = |op, width, src, dst, carry| {
+            let result = alu(op, width, src, dst, carry);
+            (result.value, result.apply(0))
+        };
+        // 0x7fff + 1 overflows into the sign bit
+        assert_eq!(
+            sr(AluOp::Add, Width::Word, 1, 0x7fff, false),
+            (0x8000, N | V)
+        );
+        // CMP equal values: no borrow, so C is set along with Z
+        assert_eq!(sr(AluOp::Cmp, Width::Word, 5, 5, false), (0, C | Z));
+        assert_eq!(sr(AluOp::Sub, Width::Byte, 1, 0x00, false), (0xff, N));
+        // 0x0199 + 0x0001 in BCD
+        assert_eq!(
+            sr(AluOp::Dadd, Width::Word, 0x0001, 0x0199, false),
+            (0x0200, 0)
+        );
+        assert_eq!(
+            sr(AluOp::Dadd, Width::Byte, 0x01, 0x99, false),
+            (0x00, C | Z)
+        );
+        // RRC shifts the old carry in at the top and the low bit out
+        assert_eq!(
+            sr(AluOp::Rrc, Width::Word, 0, 0x0001, true),
+            (0x8000, C | N)
+        );
+        assert_eq!(sr(AluOp::Rra, Width::Byte, 0, 0x81, false), (0xc0, C | N));
+        assert_eq!(
+            sr(AluOp::Sxt, Width::Word, 0, 0x0080, false),
+            (0xff80, C | N)
+        );
+    }
+
+    #[test]
+    fn emulator_clears_upper_byte_and_returns_from_interrupts() {
+        use crate::ir::GIE;
+        // 32 KB of flash so the vectors are in the image
+        let code = [0x4035, 0x1234, 0x4075, 0x0056, 0xd232, 0x4303, 0x1300];
+        let bytes = flash(&code, &[(2, 0x800c), (15, 0x8000)]); // vector 2 -> RETI
+
+        let mut machine = Machine::new();
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        machine.regs[SP as usize] = 0x0400;
+        for _ in 0..3 {
+            assert_eq!(machine.step(), Ok(Step::Ran));
+        }
+        assert_eq!(machine.regs[5], 0x0056); // MOV.B #0x56, r5
+        assert_eq!(machine.sr() & GIE, GIE);
+
+        machine.interrupt(2);
+        assert_eq!(machine.step(), Ok(Step::Interrupt));
+        assert_eq!((machine.pc(), machine.sr()), (0x800c, 0));
+        assert_eq!(machine.step(), Ok(Step::Ran));
+        assert_eq!((machine.pc(), machine.sr() & GIE), (0x800a, GIE));
+        assert_eq!(machine.regs[SP as usize], 0x0400);
+    }
+
+    #[test]
+    fn peripherals_send_bytes_and_wake_the_cpu() {
+        use crate::ir::CPUOFF;
+        use crate::{
+            device::{device, Model},
+            peripherals::{TimerA, Uart, Watchdog},
+        };
+        use std::sync::mpsc::channel;
+        let code = [
+            0x40b2, 0x5a80, 0x0120, // MOV #WDTPW|WDTHOLD, &WDTCTL
+            0xb3e2, 0x0003, 0x27fd, // BIT.B #UCA0TXIFG, &IFG2 / JEQ $-4
+            0x40f2, 0x0048, 0x0067, // MOV.B #'H', &UCA0TXBUF
+            0xb3e2, 0x0003, 0x27fd, //
+            0x40f2, 0x0069, 0x0067, // MOV.B #'i', &UCA0TXBUF
+            0x40b2, 0x0064, 0x0172, // MOV #100, &TA0CCR0
+            0x40b2, 0x0010, 0x0162, // MOV #CCIE, &TA0CCTL0
+            0x40b2, 0x0210, 0x0160, // MOV #TASSEL_2|MC_1, &TA0CTL
+            0xd032, 0x0018, // BIS #GIE|CPUOFF, SR
+            0x3fff, // JMP $
+            0x5315, // isr: INC r5
+            0xc0b1, 0x0010, 0x0000, // BIC #CPUOFF, 0(SP)
+            0x1300, // RETI
+        ];
+        let bytes = flash(&code, &[(9, 0x8036), (15, 0x8000)]); // TIMER0_A0_VECTOR
+
+        let g2553 = device("msp430g2553").unwrap();
+        let usci = Model::Usci {
+            rx_vector: 7,
+            tx_vector: 6,
+        };
+        let (_, input) = channel();
+        let (output, sent) = channel();
+        let mut machine = Machine::with_peripherals(vec![
+            Box::new(Watchdog::new(g2553, 10).unwrap()),
+            Box::new(TimerA::new(g2553, "TA0", 9, 8).unwrap()),
+            Box::new(Uart::new(g2553, usci, input, output).unwrap()),
+        ]);
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        machine.regs[SP as usize] = 0x0400;
+        for _ in 0..10_000 {
+            if machine.regs[5] != 0 {
+                break;
+            }
+            assert_ne!(machine.step(), Ok(Step::Reset));
+        }
+        assert_eq!(machine.regs[5], 1);
+        machine.step().unwrap(); // BIC
+        machine.step().unwrap(); // RETI, awake now
+        assert_eq!(machine.pc(), 0x8034);
+        assert_eq!(machine.sr() & CPUOFF, 0);
+        assert!(machine.cycles > 100);
+        assert_eq!(machine.read_word(0x0162), 0x0010); // CCIFG cleared by taking the interrupt
+        assert_eq!(sent.try_iter().collect::<Vec<u8>>(), b"Hi");
+    }
+
+    #[test]
+    fn watchdog_resets_a_cpu_that_never_stops_it() {
+        use crate::{device::device, peripherals::Watchdog};
+        let g2553 = device("msp430g2553").unwrap();
+        let ifg1 = g2553.address_of("IFG1").unwrap();
+        assert_eq!(g2553.address_of("WDTCTL"), Some(0x0120));
+        assert_eq!(g2553.address_of("NOTAREG"), None);
+        let watchdog = || Box::new(Watchdog::new(g2553, 10).unwrap());
+
+        let bytes = flash(&[0x3fff], &[(15, 0x8000)]); // JMP $
+        let mut machine = Machine::with_peripherals(vec![watchdog()]);
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        let mut steps = 0;
+        while machine.step() == Ok(Step::Ran) {
+            steps += 1;
+        }
+        // 32768 SMCLK cycles after reset, two a JMP
+        assert_eq!((steps, machine.cycles), (16383, 32768));
+        assert_eq!(machine.pc(), 0x8000);
+        assert_eq!(machine.read_byte(ifg1) & 1, 1); // WDTIFG says why
+
+        // so does writing it without the password
+        let code = [0x40b2, 0x0080, 0x0120]; // MOV #WDTHOLD, &WDTCTL
+        let bytes = flash(&code, &[(15, 0x8000)]);
+        let mut machine = Machine::with_peripherals(vec![watchdog()]);
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        assert_eq!(machine.step(), Ok(Step::Reset));
+        assert_eq!(machine.pc(), 0x8000);
+    }
+
+    #[test]
+    fn gpio_pin_changes_interrupt() {
+        use crate::{
+            device::device,
+            peripherals::{Gpio, Watchdog},
+        };
+        let code = [
+            0x40b2, 0x5a80, 0x0120, // MOV #WDTPW|WDTHOLD, &WDTCTL
+            0x4031, 0x0400, // MOV #0x0400, SP
+            0xd3d2, 0x0025, // BIS.B #BIT0, &P1IE, rising edge
+            0xd232, // BIS #GIE, SR
+            0x3fff, // JMP $
+            0x5315, // isr: INC r5
+            0xc3d2, 0x0023, // BIC.B #BIT0, &P1IFG
+            0x1300, // RETI
+        ];
+        let bytes = flash(&code, &[(2, 0x8012), (15, 0x8000)]); // PORT1_VECTOR
+        let g2553 = device("msp430g2553").unwrap();
+        let mut machine = Machine::with_peripherals(vec![
+            Box::new(Watchdog::new(g2553, 10).unwrap()),
+            Box::new(Gpio::new(g2553, &[("P1IN", 2), ("P2IN", 3)]).unwrap()),
+        ]);
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        for _ in 0..10 {
+            assert_eq!(machine.step(), Ok(Step::Ran));
+        }
+        assert_eq!(machine.pc(), 0x8010);
+
+        machine.write_byte(0x0020, 0x01); // something outside pulls P1.0 high
+        machine.step().unwrap(); // the pin is sampled after this one
+        assert_eq!(machine.read_byte(0x0023), 0x01);
+        assert_eq!(machine.step(), Ok(Step::Interrupt));
+        assert_eq!(machine.pc(), 0x8012);
+        for _ in 0..3 {
+            machine.step().unwrap(); // INC, BIC, RETI
+        }
+        assert_eq!((machine.pc(), machine.regs[5]), (0x8010, 1));
+        assert_eq!(machine.read_byte(0x0023), 0);
+
+        // staying high isn't another edge
+        for _ in 0..10 {
+            assert_eq!(machine.step(), Ok(Step::Ran));
+        }
+        assert_eq!(machine.regs[5], 1);
+    }
+
+    #[test]
+    fn devices_get_the_peripherals_their_tables_have() {
+        use crate::{
+            device::{device, Model},
+            peripherals::{TimerA, Uart},
+        };
+        use std::sync::mpsc::channel;
+        let (g2553, f2274) = (
+            device("msp430g2553").unwrap(),
+            device("msp430f2274").unwrap(),
+        );
+        let fr5969 = device("msp430fr5969").unwrap();
+        assert!(device("msp430f1611").is_none());
+        // the F2274 calls its only Timer_A plain TA
+        assert!(TimerA::new(f2274, "TA", 9, 8).is_some());
+        assert!(TimerA::new(g2553, "TA", 9, 8).is_none());
+        assert_eq!(f2274.address_of("TACCR0"), Some(0x0172));
+
+        // the listing names what the selected part has there, on the FR parts WDTCTL moved
+        let code = [0x40b2, 0x5a80, 0x0120, 0x4130]; // MOV #WDTPW|WDTHOLD, &WDTCTL / RET
+        let bytes = code.map(u16::to_le_bytes).concat();
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let named = decompile(&lines, &functions, g2553.registers);
+        assert!(named.contains("WDTCTL = 0x5a80;"), "{named}");
+        let text = decompile(&lines, &functions, fr5969.registers);
+        assert!(text.contains("PMMCTL0 = 0x5a80;"), "{text}");
+        let text = decompile(&lines, &functions, &[]);
+        assert!(text.contains("*(uint16_t *)0x0120 = 0x5a80;"), "{text}");
+
+        // eUSCI_A0 on the FR5969, one vector and UCA0IV to say why
+        let code = [
+            0x40f2, 0x0041, 0x05ce, // MOV.B #'A', &UCA0TXBUF
+            0xd392, 0x05da, // BIS #UCRXIE, &UCA0IE
+            0xd232, // BIS #GIE, SR
+            0x3fff, // JMP $
+            0x4215, 0x05de, // isr: MOV &UCA0IV, r5
+            0x1300, // RETI
+        ];
+        let bytes = flash(&code, &[(8, 0x800e), (15, 0x8000)]); // USCI_A0_VECTOR
+        let eusci = fr5969.models[1];
+        assert_eq!(eusci, Model::Eusci { vector: 8 });
+        let (received, input) = channel();
+        let (output, sent) = channel();
+        received.send(b'x').unwrap();
+        let mut machine = Machine::with_peripherals(vec![Box::new(
+            Uart::new(fr5969, eusci, input, output).unwrap(),
+        )]);
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        machine.regs[SP as usize] = 0x2400;
+        for _ in 0..20 {
+            machine.step().unwrap();
+        }
+        assert_eq!((machine.pc(), machine.regs[5]), (0x800c, 2)); // UCRXIFG
+        assert_eq!(machine.read_byte(0x05cc), b'x');
+        assert_eq!(machine.read_word(0x05dc), 0x0002); // RXIFG read out of UCA0IV, TXIFG back
+        assert_eq!(sent.try_iter().collect::<Vec<u8>>(), b"A");
+    }
+
+    #[test]
+    fn trace_files_add_up_repeated_pcs() {
+        let trace =
+            trace::Trace::parse("# from the bench\n8000\n0x8004 x12 extra\n\n8000\n").unwrap();
+        assert_eq!(trace.count(0x8000), 2);
+        assert_eq!(trace.count(0x8004), 12);
+        assert_eq!(trace.count(0x8008), 0);
+        assert_eq!(trace.to_text(), "8000 x2\n8004 x12\n");
+        // an mspdebug log has the instruction word there, not a count
+        let trace = trace::Trace::parse("8004 4303 nop\n8006 4130 ret\n").unwrap();
+        assert_eq!((trace.count(0x8004), trace.count(0x8006)), (1, 1));
+        // MSP430X PCs are 20 bits
+        assert_eq!(
+            trace::Trace::parse("1c400 x3\n").unwrap().count(0x1_c400),
+            3
+        );
+        assert!(trace::Trace::parse("zzzz\n").is_err());
+    }
+
+    #[test]
+    fn harness_calls_a_checksum_routine() {
+        use crate::harness::{Abi, CallError, Harness};
+        let code = [
+            0x4c0e, // sub_8000: MOV r12, r14
+            0x430c, // CLR r12
+            0x930d, // TST r13
+            0x2404, // JEQ done
+            0x4e7f, // loop: MOV.B @r14+, r15
+            0x5f0c, // ADD r15, r12
+            0x831d, // DEC r13
+            0x23fc, // JNE loop
+            0x4130, // done: RET
+            0x12b0, 0x8000, // main: CALL #sub_8000
+            0x3fff, // JMP $
+        ];
+        let bytes = flash(&code, &[(15, 0x8012)]);
+
+        let mut firmware = Harness::new(&bytes, BASE);
+        firmware.write_bytes(0x0200, &[0x01, 0x02, 0xff]);
+        let sum = firmware.call_named("sub_8000", &[0x0200, 3]).unwrap();
+        assert_eq!(sum.value(), 0x0102);
+        assert_eq!(firmware.call(0x8000, &[0x0200, 0]).unwrap().value(), 0);
+
+        // the legacy ABI fills the same registers the other way round
+        firmware.abi = Abi::Legacy;
+        let ret = firmware.call(0x8010, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(ret.regs[12..16], [4, 3, 2, 1]);
+        assert_eq!(ret.value(), 1);
+        assert_eq!(
+            firmware.call_named("sub_9999", &[]).unwrap_err(),
+            CallError::UnknownFunction("sub_9999".to_owned())
+        );
+    }
+
+    #[test]
+    fn browser_follows_calls_and_comes_back() {
+        use crate::{data::find_data, tui::*};
+        let code = [0x4130, 0x12b0, 0x8000, 0x3fff]; // RET / main: CALL #0x8000 / JMP $
+        let bytes = flash(&code, &[(15, 0x8002)]);
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let data = find_data(&lines, &functions, &bytes, BASE, &[]);
+        let mut browser =
+            Browser::new(&lines, &functions, &data, &bytes, BASE, &Default::default());
+
+        let mut press = |text: &str| {
+            for key in parse_keys(text.as_bytes()) {
+                browser.key(key, 10);
+            }
+            browser.address()
+        };
+        assert_eq!(press("g8002\r"), 0x8002);
+        assert_eq!(press("\r"), 0x8000); // follow the CALL
+        assert_eq!(press("\x7f"), 0x8002); // and back
+        assert_eq!(press("gsub_8000\rx\r"), 0x8002); // the one xref to sub_8000
+        assert_eq!(press("\x1b[A"), 0x8002); // up onto main's header
+        assert_eq!(press("g2\r"), 0x8002); // a file offset works too
+        assert!(browser.render(24, 80).contains("CALL"));
+    }
+
+    #[test]
+    fn annotations_round_trip_and_apply() {
+        use crate::annotations::Annotations;
+        // main: CALL #0x8008 / JMP $ / RET nothing calls / RET
+        let code = [0x12b0, 0x8008, 0x3fff, 0x4130, 0x4130];
+        let bytes = flash(&code, &[(15, 0x8000)]);
+
+        let mut notes = Annotations::parse(
+            "[labels]\n0x8008 = \"checksum\" # trailing comment\n\n[data]\n0x8004 = 0x8006\n",
+        )
+        .unwrap();
+        notes.edit("code", "0x8006..0x8008").unwrap();
+        notes.edit("comment", "0x8000=say \"hi\"").unwrap();
+        notes
+            .edit("signature", "0x8008=uint16_t checksum(void *p, int n)")
+            .unwrap();
+        assert_eq!(Annotations::parse(&notes.to_toml()).unwrap(), notes);
+        assert_eq!(notes.comments[&0x8000], "say \"hi\"");
+        // FRAM parts have code above 64K, and nothing is past 20 bits
+        assert!(Annotations::parse("[labels]\n0x14400 = \"high\"\n").is_ok());
+        assert!(Annotations::parse("[labels]\n0x100000 = \"nowhere\"\n").is_err());
+
+        let lines = analyze(&bytes, BASE);
+        let mut functions = functions::find_functions_from(
+            &lines,
+            &bytes,
+            BASE,
+            &notes.function_starts(),
+            Some(memory::VECTOR_TABLE),
+        );
+        notes.apply(&lines, &mut functions);
+        let names: Vec<String> = functions.iter().map(|f| f.name(&lines)).collect();
+        assert_eq!(names, ["reset_8000", "sub_8006", "checksum"]);
+        assert_eq!(functions[0].body, [0]); // the JMP is data now
+        assert_eq!(
+            annotations::signature_shape(functions[2].signature.as_ref().unwrap()),
+            (2, true)
+        );
+
+        // the data region comes back as data, the browser shows the notes
+        let data = data::find_data(&lines, &functions, &bytes, BASE, &notes.data_regions());
+        assert_eq!(data[0].address, Address(0x8004));
+        assert_eq!(data[0].kind, data::DataKind::Words(vec![0x3fff]));
+        let mut browser = tui::Browser::new(&lines, &functions, &data, &bytes, BASE, &notes);
+        let screen = browser.render(24, 80);
+        assert!(screen.contains("; say \"hi\"") && screen.contains("; checksum"));
+
+        // a code region's end says how far its function goes
+        let code = [0x3fff, 0x3c01, 0x4303, 0x4130, 0x4130]; // main: JMP $ / JMP $+4 / NOP / RET / RET
+        let bytes = flash(&code, &[(15, 0x8000)]);
+        let notes = Annotations::parse("[code]\n0x8002 = 0x8008\n").unwrap();
+        let lines = analyze(&bytes, BASE);
+        let mut functions = functions::find_functions_from(
+            &lines,
+            &bytes,
+            BASE,
+            &notes.function_starts(),
+            Some(memory::VECTOR_TABLE),
+        );
+        notes.apply(&lines, &mut functions);
+        let at = |indexes: &[usize]| -> Vec<u32> {
+            indexes.iter().map(|&i| lines[i].address.0).collect()
+        };
+        assert_eq!(at(&functions[1].body), [0x8002, 0x8006]);
+        assert_eq!(at(&functions[1].claimed), [0x8004]); // shown as code, never analysed
+        let data = data::find_data(&lines, &functions, &bytes, BASE, &notes.data_regions());
+        assert!(data.iter().all(|item| item.address.0 >= 0x8008));
+    }
+
+    #[test]
+    fn code_regions_past_the_flow_dont_upset_the_analyses() {
+        use crate::{annotations::Annotations, lint::lint, memory::MemoryMap, stack::*};
+        let code = [0x3fff, 0x4130, 0x4303, 0x4303]; // main: JMP $ / RET nothing reaches / padding
+        let bytes = flash(&code, &[(15, 0x8000)]);
+        let notes = Annotations::parse("[code]\n0x8000 = 0x8004\n").unwrap();
+        let lines = analyze(&bytes, BASE);
+        let mut functions = functions::find_functions_from(
+            &lines,
+            &bytes,
+            BASE,
+            &notes.function_starts(),
+            Some(memory::VECTOR_TABLE),
+        );
+        notes.apply(&lines, &mut functions);
+        assert_eq!(
+            (functions[0].body.clone(), functions[0].claimed.clone()),
+            (vec![0], vec![1])
+        );
+
+        let map = MemoryMap::device("msp430g2553").unwrap();
+        assert!(lint(&lines, &functions, &map).is_empty());
+        let infos: Vec<StackInfo> = functions
+            .iter()
+            .map(|f| analyze_function(&lines, f))
+            .collect();
+        let worst = worst_case(
+            &lines,
+            0,
+            &functions,
+            &infos,
+            &mut Vec::new(),
+            &mut std::collections::HashMap::new(),
+        );
+        assert_eq!(worst, Some(0));
+    }
+
+    #[test]
+    fn memory_maps_place_the_image_and_check_accesses() {
+        use crate::memory::*;
+        let map = MemoryMap::parse(
+            "# a made up part\n\
+             peripherals 0x0000 0x0200\n\
+             ram         0x0200 0x0400 rwx\n\
+             flash       0xc000 0x10000 r-x  main_flash\n",
+        )
+        .unwrap();
+        assert_eq!(map.image_base, 0xc000);
+        assert_eq!(map.region(0x0300).unwrap().kind, Kind::Ram);
+        assert_eq!(map.check(0x0300, Access::WRITE), None);
+        assert_eq!(
+            map.check(0xc010, Access::WRITE).unwrap(),
+            "writes 0xc010 in main_flash (r-x)"
+        );
+        assert!(map.check(0x0800, Access::READ).is_some());
+        assert!(map.check_image(0x4000).is_none());
+        assert!(map.check_image(0x4002).is_some());
+
+        let moved = MemoryMap::parse("flash 0x8000 0x10000\nbase 0x9000").unwrap();
+        assert_eq!(moved.image_base, 0x9000);
+        assert!(MemoryMap::parse("ram 0x200 0x400\nram 0x300 0x500").is_err());
+
+        let fram = MemoryMap::device("msp430fr5969").unwrap();
+        assert_eq!(fram.region(0x12000).unwrap().kind, Kind::Fram);
+        assert_eq!(fram.device.as_deref(), Some("msp430fr5969"));
+        assert_eq!(
+            MemoryMap::device(DEFAULT_DEVICE).unwrap().image_base,
+            0x8000
+        );
+
+        // the part a map file is for, and whether it leaves room for the vectors
+        let named = MemoryMap::parse(
+            "flash 0xc000 0x10000
+device msp430g2553",
+        )
+        .unwrap();
+        assert_eq!(named.device.as_deref(), Some("msp430g2553"));
+        assert_eq!(named.vectors(), Some(VECTOR_TABLE));
+        assert!(MemoryMap::parse("device msp430x9999").is_err());
+        assert_eq!(map.device, None);
+        assert_eq!(
+            MemoryMap::parse("flash 0xc000 0xffe0").unwrap().vectors(),
+            None
+        );
+
+        // one address syntax for maps, annotations and --base
+        assert_eq!(parse_address("0x8000"), Some(0x8000));
+        assert_eq!(parse_address(" 32768"), Some(0x8000));
+        assert_eq!(parse_address("0x100000"), Some(0x10_0000)); // the end of everything
+        assert_eq!(parse_address("0x100002"), None);
+        assert_eq!(parse_address("8000h"), None);
+        let notes = crate::annotations::Annotations::parse("[labels]\n32768 = \"main\"\n");
+        assert_eq!(notes.unwrap().labels[&0x8000], "main");
+
+        // an FR image runs past the vectors, they're still found
+        let mut words = vec![0x4303u16; (0x10100 - 0x4400) / 2];
+        words[0] = 0x3fff; // JMP $
+        words[(0xfffe - 0x4400) / 2] = 0x4400;
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let lines = analyze(&bytes, 0x4400);
+        let functions = functions::find_functions_from(&lines, &bytes, 0x4400, &[], fram.vectors());
+        assert_eq!(functions[0].kind, functions::FunctionKind::Vector(15));
+    }
+
+    #[test]
+    fn addresses_are_placed_at_the_image_base() {
+        assert_eq!(Address::code(0x8004), Some(Address(0x8004)));
+        assert_eq!(Address::code(0x8005), None); // an instruction can't start there
+        assert_eq!(Address::code(0xf_fffe), Some(Address(0xf_fffe)));
+        assert_eq!(Address::code(0x10_0000), None);
+        let bytes = [0x4303u16, 0x12b0, 0x8000].map(u16::to_le_bytes).concat();
+        let lines = disassemble(&bytes, 0xc000);
+        assert_eq!(lines[1].address, Address(0xc002));
+        assert_eq!(flow::call_target(&lines[1]), Some(Address(0x8000)));
+
+        // NOPs, then see where a cursor lands after each jump
+        let bytes = [0x4303u16; 8].map(u16::to_le_bytes).concat();
+        let mut cursor = Cursor::new(&bytes, BASE);
+        let next = |cursor: &mut Cursor| cursor.next().unwrap().address;
+        assert_eq!(next(&mut cursor), Address(0x8000));
+        cursor.jump_to_offset(Offset(6));
+        assert_eq!(next(&mut cursor), Address(0x8006));
+        cursor.jump_to_absolute(0x8002);
+        assert_eq!(next(&mut cursor), Address(0x8002));
+        cursor.jump_to_absolute(0x8003); // odd, ignored
+        assert_eq!(next(&mut cursor), Address(0x8004));
+        cursor.jump_to_absolute(0x7ffe); // before the image, ignored
+        assert_eq!(next(&mut cursor), Address(0x8006));
+        cursor.jump_to_absolute(0x9000); // past it, ignored
+        assert_eq!(next(&mut cursor), Address(0x8008));
+    }
+
+    // gdb's end of a session, everything it sends is queued up front
+    struct Pipe {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl std::io::Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl std::io::Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl gdb::Link for Pipe {
+        fn poll(&mut self) -> std::io::Result<Option<u8>> {
+            Ok(None)
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${data}#{sum:02x}")
+    }
+
+    // runs a session over everything gdb sends, what went back
+    fn gdb_session(bytes: &[u8], sent: &str) -> String {
+        let mut machine = Machine::new();
+        machine.load_image(bytes, BASE);
+        machine.reset();
+        let pipe = Pipe {
+            input: std::io::Cursor::new(sent.as_bytes().to_vec()),
+            output: Vec::new(),
+        };
+        let mut session = gdb::Session::new(pipe, machine);
+        session.run().unwrap();
+        String::from_utf8(session.link.output).unwrap()
+    }
+
+    #[test]
+    fn gdb_packets_are_checked_and_acked() {
+        let bytes = flash(&[0x3fff], &[(15, 0x8000)]);
+        // a corrupt packet gets a - and no answer, gdb sends it again
+        let sent = format!("$?#00{}+", packet("?"));
+        assert_eq!(gdb_session(&bytes, &sent), format!("-+{}", packet("S05")));
+        // a reply gdb naks goes out again
+        let sent = format!("{}-+", packet("?"));
+        let reply = packet("S05");
+        assert_eq!(gdb_session(&bytes, &sent), format!("+{reply}{reply}"));
+        // hex that isn't ASCII is an error, not a panic
+        let sent = format!("{}+", packet("M200,2:0\u{e9}0"));
+        assert_eq!(gdb_session(&bytes, &sent), format!("+{}", packet("E01")));
+    }
+
+    #[test]
+    fn gdb_reads_and_writes_registers_memory_and_breakpoints() {
+        // NOP, NOP, JMP $
+        let bytes = flash(&[0x4303, 0x4303, 0x3fff], &[(15, 0x8000)]);
+        let commands = [
+            ("p5", "00000000"),
+            ("P5=34120000", "OK"),
+            ("p5", "34120000"),
+            ("M200,2:abcd", "OK"),
+            ("m200,3", "abcd00"),
+            ("Z0,8004,2", "OK"),
+            ("c", "S05"),
+            ("p0", "04800000"),
+            ("s", "S05"),
+            ("p0", "04800000"), // JMP $ goes nowhere
+            ("z0,8004,2", "OK"),
+            ("Z2,8004,2", "E01"), // no watchpoints
+        ];
+        // the OK for QStartNoAckMode is the last thing acked
+        let mut sent = format!("{}+", packet("QStartNoAckMode"));
+        let mut expected = format!("+{}", packet("OK"));
+        for (command, reply) in commands {
+            sent += &packet(command);
+            expected += &packet(reply);
+        }
+        sent += &packet("k");
+        assert_eq!(gdb_session(&bytes, &sent), expected);
+    }
+
+    #[test]
+    fn stack_depths_add_up_through_calls() {
+        use crate::stack::{analyze_function, print_stack_report, sp_effect, worst_case, SpEffect};
+        let code = [
+            0x120a, // PUSH r10
+            0x8221, // SUB #4, SP
+            0x12b0, 0x8014, // CALL #leaf
+            0x5221, // ADD #4, SP
+            0x413a, // POP r10
+            0x4130, // RET
+            0x12b0, 0x800e, // recursive: CALL #recursive
+            0x4130, // RET
+            0x120b, // leaf: PUSH r11
+            0x413b, // POP r11
+            0x4130, // RET
+        ];
+        let bytes = flash(&code, &[(15, 0x8000)]);
+        let lines = analyze(&bytes, BASE);
+        let effects: Vec<SpEffect> = lines[..6].iter().map(sp_effect).collect();
+        use SpEffect::Delta;
+        assert_eq!(
+            effects,
+            [
+                Delta(2),
+                Delta(4),
+                Delta(0),
+                Delta(-4),
+                Delta(-2),
+                Delta(-2)
+            ]
+        );
+        let mov_sp = disassemble(&[0x4c01u16].map(u16::to_le_bytes).concat(), BASE); // MOV r12, SP
+        assert_eq!(sp_effect(&mov_sp[0]), SpEffect::Unknown);
+
+        let functions = find_functions(&lines, &bytes, BASE);
+        let at = |address: u32| {
+            functions
+                .iter()
+                .position(|f| lines[f.start].address == Address(address))
+                .unwrap()
+        };
+        let main = analyze_function(&lines, &functions[at(0x8000)]);
+        assert_eq!(main.frame, 6);
+        assert_eq!(main.calls, [(2, Some(Address(0x8014)))]);
+        assert_eq!(main.depth[&5], 0); // RET with everything popped
+        assert!(main.mismatches.is_empty() && main.unknown.is_empty());
+
+        let infos: Vec<_> = functions
+            .iter()
+            .map(|f| analyze_function(&lines, f))
+            .collect();
+        let worst = |index| {
+            worst_case(
+                &lines,
+                index,
+                &functions,
+                &infos,
+                &mut Vec::new(),
+                &mut std::collections::HashMap::new(),
+            )
+        };
+        assert_eq!(worst(at(0x8014)), Some(2));
+        assert_eq!(worst(at(0x8000)), Some(6 + 2 + 2)); // frame, return address, leaf
+        assert_eq!(worst(at(0x800e)), None);
+
+        // nothing to report on, but no panic either
+        print_stack_report(&[], &[]);
+    }
+
+    #[test]
+    fn switch_tables_become_branch_targets() {
+        // CMP #3, r15 / JHS 0x8030 / RLA r15, then the branch at 0x8008, and NOPs
+        let bound = [0x903f, 0x0003, 0x2c15, 0x5f0f];
+        let targets = |bound: [u16; 4], branch: &[u16], table: &[u16]| {
+            let mut code = vec![0x4303; 0x40];
+            code[..4].copy_from_slice(&bound);
+            code[4..4 + branch.len()].copy_from_slice(branch);
+            code[0x20..0x20 + table.len()].copy_from_slice(table); // at 0x8040
+            let bytes = flash(&code, &[]);
+            let lines = analyze(&bytes, BASE);
+            lines
+                .into_iter()
+                .flat_map(|l| l.targets)
+                .collect::<Vec<_>>()
+        };
+        let cases = [Address(0x8020), Address(0x8022), Address(0x8024)];
+        let table = [0x8020, 0x8022, 0x8024];
+
+        // MOV 0x8040(r15), PC
+        assert_eq!(targets(bound, &[0x4f10, 0x8040], &table), cases);
+        // ADD #0x8040, r15 / BR @r15
+        assert_eq!(targets(bound, &[0x503f, 0x8040, 0x4f20], &table), cases);
+        // ADD r15, PC and three JMP 0x8020 after it
+        assert_eq!(
+            targets(bound, &[0x5f00, 0x3c0a, 0x3c09, 0x3c08], &[]),
+            [Address(0x800a), Address(0x800c), Address(0x800e)]
+        );
+
+        // no JHS after the CMP, no RLA, an entry into the CMP's immediate, a NOP in an inline table
+        let no_jhs = [0x903f, 0x0003, 0x4303, 0x5f0f];
+        assert!(targets(no_jhs, &[0x4f10, 0x8040], &table).is_empty());
+        let no_rla = [0x903f, 0x0003, 0x2c15, 0x4303];
+        assert!(targets(no_rla, &[0x4f10, 0x8040], &table).is_empty());
+        assert!(targets(bound, &[0x4f10, 0x8040], &[0x8020, 0x8002, 0x8024]).is_empty());
+        assert!(targets(bound, &[0x5f00, 0x3c0a, 0x4303, 0x3c08], &[]).is_empty());
+    }
+
+    #[test]
+    fn cycle_counts_match_the_users_guides() {
+        use crate::cycles::{block_cycles, cycles, Cpu};
+        // instruction, SLAU144 (MSP430) and SLAU208 (MSP430X) counts
+        let table: [(&[u16], u8, u8); 28] = [
+            (&[0x4508], 1, 1),                 // MOV r5, r8
+            (&[0x4500], 2, 3),                 // MOV r5, pc
+            (&[0x4305], 1, 1),                 // MOV #0, r5, the constant generator is a register
+            (&[0x4035, 0x1234], 2, 2),         // MOV #0x1234, r5
+            (&[0x4528], 2, 2),                 // MOV @r5, r8
+            (&[0x4538], 2, 2),                 // MOV @r5+, r8
+            (&[0x4130], 3, 3),                 // RET
+            (&[0x4518, 0x0002], 3, 3),         // MOV 2(r5), r8
+            (&[0x4588, 0x0002], 4, 3),         // MOV r5, 2(r8), MOV doesn't read it on the X
+            (&[0x5588, 0x0002], 4, 4),         // ADD r5, 2(r8)
+            (&[0x45a8, 0x0002], 5, 4),         // MOV @r5, 2(r8)
+            (&[0x4598, 0x0002, 0x0004], 6, 5), // MOV 2(r5), 4(r8)
+            (&[0x5598, 0x0002, 0x0004], 6, 6), // ADD 2(r5), 4(r8)
+            (&[0x1105], 1, 1),                 // RRA r5
+            (&[0x1125], 3, 3),                 // RRA @r5
+            (&[0x1115, 0x0002], 4, 4),         // RRA 2(r5)
+            (&[0x1205], 3, 3),                 // PUSH r5
+            (&[0x1225], 4, 3),                 // PUSH @r5
+            (&[0x1235], 5, 3),                 // PUSH @r5+
+            (&[0x1230, 0x1234], 4, 3),         // PUSH #0x1234
+            (&[0x1215, 0x0002], 5, 4),         // PUSH 2(r5)
+            (&[0x1285], 4, 4),                 // CALL r5
+            (&[0x12a5], 4, 4),                 // CALL @r5
+            (&[0x12b0, 0x8000], 5, 4),         // CALL #0x8000
+            (&[0x1295, 0x0002], 5, 5),         // CALL 2(r5)
+            (&[0x1292, 0x0200], 5, 6),         // CALL &0x0200
+            (&[0x1300], 5, 5),                 // RETI
+            (&[0x3c00], 2, 2),                 // JMP
+        ];
+        for (words, msp430, msp430x) in table {
+            let lines = disassemble(
+                &words
+                    .iter()
+                    .flat_map(|w| w.to_le_bytes())
+                    .collect::<Vec<_>>(),
+                BASE,
+            );
+            let raw = &lines[0].raw;
+            assert_eq!(
+                cycles(raw, Cpu::MSP430),
+                Some(msp430),
+                "{words:04x?} on the MSP430"
+            );
+            assert_eq!(
+                cycles(raw, Cpu::MSP430X),
+                Some(msp430x),
+                "{words:04x?} on the MSP430X"
+            );
+        }
+
+        // MOV #0x1234, r5 / JNE back to the MOV / RET, a block ends at the JNE and at the RET
+        let bytes = [0x4035u16, 0x1234, 0x23fd, 0x4130]
+            .map(u16::to_le_bytes)
+            .concat();
+        let lines = disassemble(&bytes, BASE);
+        assert_eq!(block_cycles(&lines, Cpu::MSP430), [(1, 4), (2, 3)]);
+    }
+
+    #[test]
+    fn stats_count_code_and_data_apart() {
+        use crate::{data::find_data, stats::Stats};
+        let code = [
+            0x4035, 0x1234, // MOV #0x1234, r5
+            0x4566, // MOV.B @r5, r6
+            0x5316, // INC r6
+            0x4130, // RET
+        ];
+        let mut bytes = code.map(u16::to_le_bytes).concat();
+        bytes.extend(b"Hi!!\0\0");
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let data = find_data(&lines, &functions, &bytes, BASE, &[]);
+        let stats = Stats::collect(&lines, &functions, &data);
+
+        let count =
+            |map: &std::collections::BTreeMap<String, usize>, key: &str| map.get(key).copied();
+        assert_eq!((stats.instructions, stats.emulated), (4, 2));
+        assert_eq!((stats.byte_ops, stats.word_ops), (1, 3));
+        assert_eq!(stats.extension_words, [3, 1, 0]);
+        assert_eq!(stats.opcodes.len(), 4);
+        for opcode in ["MOV", "MOV.B", "INC", "RET"] {
+            assert_eq!(count(&stats.opcodes, opcode), Some(1), "{opcode}");
+        }
+        assert_eq!(count(&stats.source_modes, "#N"), Some(1));
+        assert_eq!(count(&stats.source_modes, "@Rn"), Some(1));
+        assert_eq!(
+            count(&stats.source_modes, "#N (constant generator)"),
+            Some(1)
+        );
+        assert_eq!(count(&stats.source_modes, "@Rn+"), Some(1)); // RET is MOV @SP+, PC
+        assert_eq!(count(&stats.dest_modes, "Rn"), Some(4));
+        // the string and its odd padding byte aren't instructions
+        assert_eq!((stats.code_bytes, stats.data_bytes), (10, 6));
+        assert_eq!(count(&stats.data_kinds, "strings"), Some(5));
+        assert_eq!(count(&stats.data_kinds, "bytes"), Some(1));
+    }
+
+    #[test]
+    fn diffs_line_up_code_that_moved() {
+        use crate::{
+            annotations::Annotations,
+            diff::{diff_images, lcs, split_functions, Edit, Key},
+            memory::{MemoryMap, DEFAULT_DEVICE},
+        };
+        let map = MemoryMap::device(DEFAULT_DEVICE).unwrap();
+        let none = Annotations::default();
+        assert_eq!(
+            lcs(b"abcd", b"acde"),
+            [
+                Edit::Same(0, 0),
+                Edit::Removed(1),
+                Edit::Same(2, 1),
+                Edit::Same(3, 2),
+                Edit::Added(3),
+            ]
+        );
+
+        // MOV #f, r12 / CALL #f / RET / NOP / f: RET, then the same with an INC r5 up front
+        let old = [0x403c, 0x800c, 0x12b0, 0x800c, 0x4130, 0x4303, 0x4130];
+        let new = [
+            0x5315, 0x403c, 0x800e, 0x12b0, 0x800e, 0x4130, 0x4303, 0x4130,
+        ];
+        let (old, new) = (
+            old.map(u16::to_le_bytes).concat(),
+            new.map(u16::to_le_bytes).concat(),
+        );
+        let (old_lines, new_lines) = (analyze(&old, BASE), analyze(&new, BASE));
+        let prints = |lines: &[globals::Line], bytes: &[u8]| -> Vec<Vec<String>> {
+            split_functions(lines, bytes, &none, &map)
+                .iter()
+                .map(|chunk| chunk.fingerprint())
+                .collect()
+        };
+        let (old_prints, new_prints) = (prints(&old_lines, &old), prints(&new_lines, &new));
+        // f is untouched even though it moved, the caller only gained the INC
+        assert_eq!(
+            lcs(&old_prints, &new_prints),
+            [Edit::Added(0), Edit::Removed(0), Edit::Same(1, 1)]
+        );
+        // the NOP after RET is padding, not part of the caller
+        assert_eq!(
+            lcs(&old_prints[0], &new_prints[0]),
+            [
+                Edit::Added(0),
+                Edit::Same(0, 1),
+                Edit::Same(1, 2),
+                Edit::Same(2, 3),
+            ]
+        );
+        // an immediate outside the image is a value, not an address, and is kept
+        let bytes = [0x403cu16, 0x0200].map(u16::to_le_bytes).concat(); // MOV #0x200, r12
+        assert!(prints(&analyze(&bytes, BASE), &bytes)[0][0].contains("0x200"));
+
+        // MOV &table, r12 / MOV table, r13 / MOV &0x0200, r14 / RET / padding / table: .word 1
+        // then moved up two bytes, only the RAM address is left to tell them apart
+        let image = |at: u16, ram: u16| {
+            let mut code = vec![0x4303; (at - 0x800e) as usize / 2];
+            let symbolic = at.wrapping_sub(0x8006); // relative to the word after the opcode
+            code.splice(0..0, [0x421c, at, 0x401d, symbolic, 0x421e, ram, 0x4130]);
+            code.push(1);
+            code.iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect::<Vec<u8>>()
+        };
+        let (old, new) = (image(0x8020, 0x0200), image(0x8022, 0x0200));
+        let (old_lines, new_lines) = (analyze(&old, BASE), analyze(&new, BASE));
+        let (old_prints, new_prints) = (prints(&old_lines, &old), prints(&new_lines, &new));
+        assert_eq!(old_prints, new_prints);
+        // the padding and the table aren't part of the function
+        assert_eq!(old_prints[0].len(), 4);
+        let other = image(0x8020, 0x0202);
+        assert_ne!(prints(&analyze(&other, BASE), &other), old_prints);
+
+        // with labels, a function that changed still lines up with itself
+        let old = [0x12b0, 0x8006, 0x3fff, 0x4303, 0x4130]
+            .map(u16::to_le_bytes)
+            .concat();
+        let new = [0x12b0, 0x8006, 0x3fff, 0x5315, 0x4130]
+            .map(u16::to_le_bytes)
+            .concat();
+        let (old_lines, new_lines) = (analyze(&old, BASE), analyze(&new, BASE));
+        let notes = Annotations::parse("[labels]\n0x8006 = \"tick\"\n").unwrap();
+        let keys = |lines: &[globals::Line], bytes: &[u8], notes| -> Vec<Key> {
+            split_functions(lines, bytes, notes, &map)
+                .iter()
+                .map(|chunk| chunk.key())
+                .collect()
+        };
+        assert_eq!(
+            lcs(
+                &keys(&old_lines, &old, &notes),
+                &keys(&new_lines, &new, &notes)
+            ),
+            [Edit::Same(0, 0), Edit::Same(1, 1)]
+        );
+        let text = diff_images((&old_lines, &old, &notes), (&new_lines, &new, &notes), &map);
+        assert!(text.starts_with("@@ tick -> tick @@\n"), "{text}");
+        assert!(text.ends_with("1 functions unchanged\n"), "{text}");
+        // without them it's a different body, so one went and another came
+        let text = diff_images((&old_lines, &old, &none), (&new_lines, &new, &none), &map);
+        assert!(text.starts_with("@@ sub_8006 -> sub_8006 @@\n"), "{text}");
+
+        // a long run of the same thing on both sides doesn't need a table the size of both
+        let (a, b) = (vec![0u8; 50_000], [vec![1u8], vec![0; 50_000]].concat());
+        let edits = lcs(&a, &b);
+        assert_eq!(edits.len(), 50_001);
+        assert_eq!(edits[0], Edit::Added(0));
+    }
+
+    #[test]
+    fn function_starts_come_from_every_kind_of_evidence() {
+        use crate::functions::FunctionKind::{self, *};
+        let code = [
+            0x12b0, 0x8006, // reset: CALL #f
+            0x3fff, // JMP $
+            0x4130, // f: RET
+            0x120a, // PUSH r10, a prologue right after a RET
+            0x413a, // POP r10
+            0x4130, // RET
+            0x4c0d, // MOV r12, r13, nothing points here but it follows a RET and returns
+            0x4130, // RET
+            0x1300, // isr: RETI
+        ];
+        let bytes = flash(&code, &[(2, 0x8012), (15, 0x8000)]);
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let found: Vec<(u32, FunctionKind, usize)> = functions
+            .iter()
+            .map(|f| (lines[f.start].address.0, f.kind, f.body.len()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0x8000, Vector(15), 2),
+                (0x8006, Called, 1),
+                (0x8008, Prologue, 3),
+                (0x800e, AfterReturn, 2),
+                (0x8012, Vector(2), 1),
+            ]
+        );
+        assert_eq!(functions[0].name(&lines), "reset_8000");
+        assert_eq!(functions[4].name(&lines), "isr_8012");
+    }
+
+    #[test]
+    fn constants_resolve_indirect_calls_and_branches() {
+        let mut code = vec![0x4130; 0x21]; // RETs to land on
+        code[..19].copy_from_slice(&[
+            0x403f, 0x8030, // MOV #0x8030, r15
+            0x128f, // CALL r15
+            0x403d, 0x8040, // MOV #0x8040, r13
+            0x12ad, // CALL @r13, the word at 0x8040
+            0x403e, 0x8000, // MOV #0x8000, r14
+            0x503e, 0x0034, // ADD #0x34, r14
+            0x4e00, // BR r14
+            0x403c, 0x8030, // MOV #0x8030, r12
+            0x12b0, 0x8030, // CALL #0x8030, which is free to change r12
+            0x128c, // CALL r12
+            0x421a, 0x0200, // MOV &0x0200, r10, RAM could hold anything
+            0x128a, // CALL r10
+        ]);
+        code[0x20] = 0x8032;
+        code.extend([
+            0x4303, // 0x8032 at 0x8040 reads as SUB #imm, SR, this is its immediate
+            0x403b, 0x8030, // MOV #0x8030, r11
+            0x12b0, 0x8030, // CALL #0x8030, the EABI lets it change r11 too
+            0x128b, // CALL r11
+            0x4259, 0x8041, // MOV.B &0x8041, r9, the high byte of 0x8032
+            0x1089, // SWPB r9
+            0x1289, // CALL r9
+        ]);
+        let lines = analyze(&flash(&code, &[]), BASE);
+        let resolved: Vec<(u32, Vec<Address>)> = lines
+            .iter()
+            .filter(|line| !line.targets.is_empty())
+            .map(|line| (line.address.0, line.targets.clone()))
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                (0x8004, vec![Address(0x8030)]),
+                (0x800a, vec![Address(0x8032)]),
+                (0x8014, vec![Address(0x8034)]),
+                (0x8054, vec![Address(0x8000)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreached_bytes_become_strings_padding_and_tables() {
+        use crate::data::{directive, find_data, DataKind};
+        let code = [
+            0x403c, 0x8008, // MOV #0x8008, r12, the string's address
+            0x4130, // RET
+            0x4130, // f: RET
+        ];
+        let mut bytes = code.map(u16::to_le_bytes).concat();
+        bytes.extend(b"Hello\0"); // 0x8008
+        bytes.extend([0xff; 4]); // 0x800e, erased flash
+        bytes.extend([0x8006u16, 0x8006].map(u16::to_le_bytes).concat()); // 0x8012, pointers to f
+        bytes.extend(0x1234u16.to_le_bytes()); // 0x8016
+        bytes.push(0x42); // 0x8018, an odd byte at the end
+
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let items = find_data(&lines, &functions, &bytes, BASE, &[]);
+        let found: Vec<(u32, &DataKind)> = items.iter().map(|i| (i.address.0, &i.kind)).collect();
+        assert_eq!(
+            found,
+            [
+                (0x8008, &DataKind::Ascii(b"Hello\0".to_vec())),
+                (0x800e, &DataKind::Fill(2)),
+                (0x8012, &DataKind::Pointers(vec![0x8006, 0x8006])),
+                (0x8016, &DataKind::Words(vec![0x1234])),
+                (0x8018, &DataKind::Byte(0x42)),
+            ]
+        );
+        assert_eq!(items[0].xrefs, [Address(0x8000)]);
+        assert_eq!(directive(&items[0].kind), ".ascii \"Hello\\0\"");
+        assert_eq!(directive(&items[1].kind), ".fill  2, 2, 0xffff");
+    }
+
+    #[test]
+    fn lint_finds_stack_isr_and_memory_mistakes() {
+        use crate::{lint::lint, memory::MemoryMap};
+        let code = [
+            0x120a, // reset: PUSH r10
+            0x4c82, 0x8100, // MOV r12, &0x8100, flash isn't writable
+            0x4130, // RET, with r10 still pushed
+            0x120d, // good isr: PUSH r13
+            0x431d, // MOV #1, r13
+            0x413d, // POP r13
+            0x1300, // RETI
+            0x432e, // bad isr: MOV #2, r14 before it's saved
+            0x120e, // PUSH r14
+            0x431e, // MOV #1, r14
+            0x413e, // POP r14
+            0x431c, // MOV #1, r12, never saved
+            0x1300, // RETI
+        ];
+        let bytes = flash(&code, &[(2, 0x8008), (3, 0x8010), (15, 0x8000)]);
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let map = MemoryMap::device("msp430f2274").unwrap();
+        let warnings: Vec<(u32, String)> = lint(&lines, &functions, &map)
+            .into_iter()
+            .map(|w| (w.address, w.message))
+            .collect();
+        let expected = [
+            (0x8002, "writes 0x8100 in flash (r-x)"),
+            (0x8006, "returns with 2 bytes still pushed"),
+            (0x8010, "interrupt handler modifies r14 without saving it"),
+            (0x8018, "interrupt handler modifies r12 without saving it"),
+        ];
+        assert_eq!(warnings, expected.map(|(a, m)| (a, m.to_owned())));
+
+        // saving r12-r15 around a call isn't enough, an EABI callee can change r11 too
+        let code = [
+            0x3fff, // reset: JMP $
+            0x120f, 0x120e, 0x120d, 0x120c, // isr: PUSH r15 .. PUSH r12
+            0x12b0, 0x8018, // CALL #f
+            0x413c, 0x413d, 0x413e, 0x413f, // POP r12 .. POP r15
+            0x1300, // RETI
+            0x4130, // f: RET
+        ];
+        let bytes = flash(&code, &[(2, 0x8002), (15, 0x8000)]);
+        let lines = analyze(&bytes, BASE);
+        let functions = find_functions(&lines, &bytes, BASE);
+        let warnings: Vec<(u32, String)> = lint(&lines, &functions, &map)
+            .into_iter()
+            .map(|w| (w.address, w.message))
+            .collect();
+        assert_eq!(
+            warnings,
+            [(
+                0x800a,
+                "interrupt handler modifies r11 without saving it".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn profiles_charge_callers_through_the_shadow_stack() {
+        use crate::profile::Profile;
+        let code = [
+            0x40b2, 0x5a80, 0x0120, // reset: MOV #WDTPW|WDTHOLD, &WDTCTL
+            0x4031, 0x0400, // MOV #0x0400, SP
+            0x12b0, 0x8010, // CALL #f
+            0x3fff, // JMP $
+            0x12b0, 0x8016, // f: CALL #g
+            0x4130, // RET
+            0x4303, // g: NOP
+            0x4130, // RET
+        ];
+        let bytes = flash(&code, &[(15, 0x8000)]);
+        let profile = Profile::record(&bytes, BASE, 10, Vec::new());
+        let cost = |address: u32| profile.functions[&address];
+        let (main, f, g) = (cost(0x8000), cost(0x8010), cost(0x8016));
+
+        assert_eq!((main.calls, f.calls, g.calls), (1, 1, 1));
+        assert_eq!(profile.calls[&(0x8000, 0x8010)].count, 1);
+        assert_eq!(profile.calls[&(0x8010, 0x8016)].count, 1);
+        assert_eq!(
+            g.own,
+            profile.instructions[&0x8016].cycles + profile.instructions[&0x8018].cycles
+        );
+        assert_eq!(g.inclusive, g.own);
+        assert_eq!(f.inclusive, f.own + g.inclusive);
+        assert_eq!(profile.calls[&(0x8010, 0x8016)].cycles, g.inclusive);
+        assert_eq!(profile.calls[&(0x8000, 0x8010)].cycles, f.inclusive);
+        assert_eq!(main.inclusive, profile.total);
+        assert_eq!(main.own + f.inclusive, profile.total);
+        assert_eq!(profile.instructions[&0x800e].count, 3); // the rest went to JMP $
+        assert_eq!(profile.asleep, 0);
+    }
+
+    #[test]
+    fn profiles_count_a_watchdog_reset_as_a_call() {
+        use crate::{device::device, peripherals::bus, profile::Profile};
+        let bytes = flash(&[0x3fff], &[(15, 0x8000)]); // JMP $ with the watchdog left running
+        let steps = 40_000;
+        let g2553 = device("msp430g2553").unwrap();
+
+        let mut machine = Machine::with_peripherals(bus(g2553).unwrap());
+        machine.load_image(&bytes, BASE);
+        machine.reset();
+        let mut resets = 0;
+        machine.run(steps, |_, stepped| {
+            resets += (stepped.step == Step::Reset) as u64
+        });
+        assert!(resets > 0);
+
+        let profile = Profile::record(&bytes, BASE, steps, bus(g2553).unwrap());
+        assert_eq!(profile.functions[&0x8000].calls, 1 + resets);
+        assert_eq!(profile.functions[&0x8000].inclusive, profile.total);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::{
+    flow::{index_by_address, successors},
+    functions::{is_return, Function, FunctionKind},
+    globals::{Line, PC, SP, SR},
+    ir::{lift, AluOp, Ir, Operand},
+    liveness::{RegSet, CALL_CLOBBERED},
+    memory::{Access, MemoryMap},
+    stack::analyze_function,
+};
+
+/*
+    Checks for the mistakes that keep showing up in hand written assembly:
+        a path to RET/RETI that pushed more or less than it popped
+        an interrupt handler changing a register the interrupted code still needs,
+            anywhere it isn't pushed on every path leading there (or was popped again)
+        an &absolute access or a CALL/BR #constant the memory map doesn't allow
+*/
+
+// general purpose registers an instruction writes, PC/SP/SR are checked elsewhere
+fn written_registers(line: &Line) -> Vec<u8> {
+    let mut written = Vec::new();
+    let mut moved = |operand: Operand| {
+        if let Operand::IndirectIncrement(reg) = operand {
+            written.push(reg);
+        }
+    };
+    match lift(line) {
+        Ir::Alu { op, src, dst, .. } => {
+            moved(src);
+            // MOV rN, rN is a NOP
+            let nop = op == AluOp::Mov && src == dst;
+            if let (Operand::Reg(reg), true) = (dst, op.writes_result() && !nop) {
+                written.push(reg);
+            }
+        }
+        Ir::Push { src, .. } => moved(src),
+        Ir::Call { target } => {
+            moved(target);
+            written.extend(CALL_CLOBBERED);
+        }
+        _ => (),
+    }
+    written.retain(|&reg| reg > SR);
+    written
+}
+
+// registers pushed on every path from the start of the function to each line (before it runs),
+// a POP hands the register back to the interrupted code
+fn saved_before(lines: &[Line], function: &Function) -> HashMap<usize, RegSet> {
+    let by_address = index_by_address(lines);
+    let mut saved: HashMap<usize, RegSet> = HashMap::new();
+    let mut queue = vec![(function.start, RegSet::default())];
+    while let Some((i, incoming)) = queue.pop() {
+        if function.body.binary_search(&i).is_err() {
+            continue;
+        }
+        // where paths join only what both saved counts, sets only shrink so this ends
+        let before = match saved.get(&i) {
+            Some(&seen) if seen.intersection(incoming) == seen => continue,
+            Some(&seen) => seen.intersection(incoming),
+            None => incoming,
+        };
+        saved.insert(i, before);
+
+        let mut after = before;
+        match lift(&lines[i]) {
+            Ir::Push {
+                src: Operand::Reg(reg),
+                ..
+            } => after.insert(reg),
+            Ir::Alu {
+                op: AluOp::Mov,
+                src: Operand::IndirectIncrement(SP),
+                dst: Operand::Reg(reg),
+                ..
+            } => after = after.without(RegSet::of(&[reg])),
+            _ => (),
+        }
+        for next in successors(lines, i, &by_address) {
+            queue.push((next, after));
+        }
+    }
+    saved
+}
+
+// fixed addresses an instruction touches, &absolute and symbolic operands both end up Absolute
+fn fixed_accesses(line: &Line) -> Vec<(u32, Access)> {
+    let mut accesses = Vec::new();
+    match lift(line) {
+        Ir::Alu { op, src, dst, .. } => {
+            // the single operand ones have the same operand twice, that's one access
+            if let (Operand::Absolute(address), true) = (src, src != dst) {
+                accesses.push((address as u32, Access::READ));
+            }
+            match (op, src, dst) {
+                (_, _, Operand::Absolute(address)) => {
+                    let access = match op.writes_result() {
+                        true => Access::WRITE,
+                        false => Access::READ,
+                    };
+                    accesses.push((address as u32, access));
+                }
+                // MOV #constant, PC is a branch
+                (AluOp::Mov, Operand::Imm(address), Operand::Reg(PC)) => {
+                    accesses.push((address as u32, Access::EXECUTE))
+                }
+                _ => (),
+            }
+        }
+        Ir::Push {
+            src: Operand::Absolute(address),
+            ..
+        }
+        | Ir::Call {
+            target: Operand::Absolute(address),
+        } => accesses.push((address as u32, Access::READ)),
+        Ir::Call {
+            target: Operand::Imm(address),
+        } => accesses.push((address as u32, Access::EXECUTE)),
+        _ => (),
+    }
+    accesses
+}
+
+pub struct Warning {
+    pub function: String,
+    pub address: u32,
+    pub message: String,
+}
+
+pub fn lint(lines: &[Line], functions: &[Function], map: &MemoryMap) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    for function in functions {
+        let name = function.name(lines);
+        let stack = analyze_function(lines, function);
+        let mut warn = |line: usize, message: String| {
+            warnings.push(Warning {
+                function: name.clone(),
+                address: lines[line].address.0,
+                message,
+            })
+        };
+
+        for &i in &function.body {
+            for (address, access) in fixed_accesses(&lines[i]) {
+                if let Some(problem) = map.check(address, access) {
+                    warn(i, problem);
+                }
+            }
+        }
+        for &line in &stack.mismatches {
+            warn(line, "reached with different stack depths".to_owned());
+        }
+        for &i in &function.body {
+            let depth = match stack.depth.get(&i) {
+                Some(&depth) if depth != 0 && is_return(&lines[i].instruction) => depth,
+                _ => continue,
+            };
+            let what = if depth > 0 {
+                "still pushed"
+            } else {
+                "popped too many"
+            };
+            warn(i, format!("returns with {} bytes {what}", depth.abs()));
+        }
+
+        // vector targets that return with RETI, a reset handler never returns
+        let is_isr = matches!(function.kind, FunctionKind::Vector(v) if v != 15)
+            && function
+                .body
+                .iter()
+                .any(|&i| matches!(lift(&lines[i]), Ir::Reti));
+        if !is_isr {
+            continue;
+        }
+        let saved = saved_before(lines, function);
+        for &i in &function.body {
+            let Some(&saved) = saved.get(&i) else {
+                continue; // not reached from the start
+            };
+            let clobbered: Vec<String> = written_registers(&lines[i])
+                .into_iter()
+                .filter(|&reg| !saved.contains(reg))
+                .map(|reg| format!("r{reg}"))
+                .collect();
+            if !clobbered.is_empty() {
+                warn(
+                    i,
+                    format!(
+                        "interrupt handler modifies {} without saving it",
+                        clobbered.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+    warnings
+}
+
+pub fn print_lint(lines: &[Line], functions: &[Function], map: &MemoryMap) {
+    let warnings = lint(lines, functions, map);
+    for warning in &warnings {
+        println!(
+            "{:04x} {}: {}",
+            warning.address, warning.function, warning.message
+        );
+    }
+    println!("{} warning(s)", warnings.len());
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::{
+    annotations::Annotations,
+    cycles::{block_cycles, cycles},
+    data::{directive, immediate_address, reached_lines, DataItem, DataKind},
+    functions::{function_starts, Function},
+    globals::Line,
+    liveness::liveness,
+    options::Options,
+    trace::Trace,
+};
+
+// code and data interleaved in address order
+enum Entry<'d> {
+    Code(usize),
+    Data(&'d DataItem),
+}
+
+// with a trace every line says how often it ran
+pub fn print_listing(
+    lines: &[Line],
+    functions: &[Function],
+    data: &[DataItem],
+    options: &Options,
+    trace: Option<&Trace>,
+    notes: &Annotations,
+) {
+    let starts = function_starts(functions);
+    let names: HashMap<u32, String> = functions
+        .iter()
+        .map(|f| (lines[f.start].address.0, f.name(lines)))
+        .collect();
+    let reached = reached_lines(functions);
+    let data_at: HashMap<u32, &DataItem> = data.iter().map(|item| (item.address.0, item)).collect();
+
+    let blocks = match options.cycles {
+        true => block_cycles(lines, options.cpu),
+        false => Vec::new(),
+    };
+    let runs = |i: usize| trace.map(|t| t.count(lines[i].address.0));
+    let live = match options.live {
+        true => liveness(lines),
+        false => Vec::new(),
+    };
+
+    let mut entries: Vec<(u32, Entry)> = (0..lines.len())
+        .filter(|i| reached.contains(i))
+        .map(|i| (lines[i].address.0, Entry::Code(i)))
+        .chain(data.iter().map(|item| (item.address.0, Entry::Data(item))))
+        .collect();
+    entries.sort_by_key(|(address, _)| *address);
+
+    for (_, entry) in entries {
+        let i = match entry {
+            Entry::Code(i) => i,
+            Entry::Data(item) => {
+                print_data(item, &names);
+                continue;
+            }
+        };
+        let line = &lines[i];
+        let instruction = line.instruction;
+
+        if let Some(function) = starts.get(&i) {
+            let coverage = match trace {
+                Some(_) => {
+                    let ran = function.body.iter().filter(|&&i| runs(i) > Some(0)).count();
+                    format!(", {}% executed", ran * 100 / function.body.len().max(1))
+                }
+                None => "".to_owned(),
+            };
+            println!();
+            println!(
+                "; {} ({:?}), {:#06x}-{:#06x}{coverage}",
+                function.name(lines),
+                function.kind,
+                line.address.0,
+                lines[function.end - 1].address.0
+            );
+            if let Some(prototype) = &function.signature {
+                println!("; {prototype}");
+            }
+        } else if let Some(label) = notes.label(line) {
+            println!("{label}:");
+        }
+
+        let mut comment = match immediate_address(&instruction).and_then(|a| data_at.get(&a)) {
+            Some(item) => match &item.kind {
+                DataKind::Ascii(_) => format!("   ; {}", &directive(&item.kind)[7..]),
+                _ => format!("   ; data at {:#06x}", item.address.0),
+            },
+            None if !line.targets.is_empty() => {
+                let targets: Vec<String> = line
+                    .targets
+                    .iter()
+                    .map(|a| format!("{:#06x}", a.0))
+                    .collect();
+                format!("   ; -> {}", targets.join(", "))
+            }
+            None => "".to_owned(),
+        };
+        if let Some(text) = notes.comment(line) {
+            comment += &format!("   ; {text}");
+        }
+        if let Some(live) = live.get(i) {
+            comment += &format!("   ; live {live}");
+        }
+        match runs(i) {
+            Some(0) => comment += "   ; never executed",
+            Some(count) => comment += &format!("   ; ran {count}x"),
+            None => (),
+        }
+
+        if options.cycles {
+            let cycles = match cycles(&line.raw, options.cpu) {
+                Some(cycles) => cycles.to_string(),
+                None => "?".to_owned(),
+            };
+            println!(
+                "{:04x}   {:<14}   {cycles:>2}   {instruction}{comment}",
+                line.address.0, line.words
+            );
+        } else {
+            println!(
+                "{:04x}   {}       {instruction}{comment}",
+                line.address.0, line.words
+            );
+        }
+
+        if let Some((_, total)) = blocks.iter().find(|(end, _)| *end == i) {
+            println!("                        ; {total} cycles");
+        }
+    }
+}
+
+fn print_data(item: &DataItem, names: &HashMap<u32, String>) {
+    let mut comments = Vec::new();
+    if let DataKind::Pointers(words) = &item.kind {
+        let targets: Vec<&str> = words
+            .iter()
+            .map(|w| {
+                names
+                    .get(&u32::from(*w))
+                    .map_or("code", |name| name.as_str())
+            })
+            .collect();
+        comments.push(format!("-> {}", targets.join(", ")));
+    }
+    if !item.xrefs.is_empty() {
+        let xrefs: Vec<String> = item.xrefs.iter().map(|a| format!("{:#06x}", a.0)).collect();
+        comments.push(format!("xref {}", xrefs.join(", ")));
+    }
+
+    let directive = directive(&item.kind);
+    if comments.is_empty() {
+        println!("{:04x}   {directive}", item.address.0);
+    } else {
+        println!(
+            "{:04x}   {directive:<40} ; {}",
+            item.address.0,
+            comments.join(", ")
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    flow::{block_starts, ends_block, index_by_address, successors},
+    globals::{JmpOpcode, Line, PC, SP, SR},
+    ir::{lift, AluOp, Flags, Ir, Operand, C, N, V, Z},
+};
+
+/*
+    Reads and writes are worked out from ir::lift, so emulated instructions
+    are just what they're built from: RLC is ADDC rN, rN and reads C.
+
+    PC is left out of everything except instructions that load it, every
+    instruction moves it anyway. Flags are tracked on their own so a CMP
+    followed by a JEQ shows the Z dependency; for liveness a flag read
+    counts as reading SR.
+
+    Calls follow the msp430-gcc ABI: arguments come in r12-r15, the callee
+    may trash r11-r15 (CALL_CLOBBERED), and r4-r10 have to survive until
+    RET. RETI goes back to code that could have been using any of
+    r4-r15, so they're all live up to it.
+*/
+
+const CG: u8 = 3;
+
+// registers a callee may trash, r11 only under the EABI but assume the worst
+pub const CALL_CLOBBERED: [u8; 5] = [11, 12, 13, 14, 15];
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RegSet(pub u16); // bit n is rn
+
+impl RegSet {
+    pub const ALL: RegSet = RegSet(0xffff);
+
+    pub fn of(regs: &[u8]) -> RegSet {
+        RegSet(regs.iter().fold(0, |set, reg| set | 1 << reg))
+    }
+    pub fn contains(self, reg: u8) -> bool {
+        self.0 & 1 << reg != 0
+    }
+    pub fn insert(&mut self, reg: u8) {
+        self.0 |= 1 << reg;
+    }
+    pub fn union(self, other: RegSet) -> RegSet {
+        RegSet(self.0 | other.0)
+    }
+    pub fn without(self, other: RegSet) -> RegSet {
+        RegSet(self.0 & !other.0)
+    }
+    pub fn intersection(self, other: RegSet) -> RegSet {
+        RegSet(self.0 & other.0)
+    }
+}
+
+impl fmt::Display for RegSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regs: Vec<String> = (0..16)
+            .filter(|&reg| self.contains(reg))
+            .map(|reg| format!("r{reg}"))
+            .collect();
+        f.pad(&regs.join(" "))
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct DefUse {
+    pub reads: RegSet,
+    pub writes: RegSet,
+    pub flags_read: Flags,
+    pub flags_written: Flags,
+}
+
+// registers r4-r10 a callee has to give back untouched
+const CALLEE_SAVED: [u8; 7] = [4, 5, 6, 7, 8, 9, 10];
+const ARGUMENTS: [u8; 4] = [12, 13, 14, 15];
+// everything the interrupted code might have had in a register when an ISR took over
+const INTERRUPTED: [u8; 12] = [4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+impl DefUse {
+    fn read(&mut self, reg: u8) {
+        self.reads.insert(reg);
+    }
+    fn write(&mut self, reg: u8) {
+        self.writes.insert(reg);
+        if reg == SR {
+            self.flags_written = Flags::ALL;
+        }
+    }
+
+    // the registers an operand needs to work out its address or value, and the
+    // autoincrement it does. `value` is false for destinations that are only written
+    fn operand(&mut self, operand: Operand, value: bool) {
+        match operand {
+            Operand::Reg(reg) if value => {
+                self.read(reg);
+                if reg == SR {
+                    self.flags_read = Flags::ALL;
+                }
+            }
+            Operand::Indexed(reg, _) | Operand::Indirect(reg) => self.read(reg),
+            Operand::IndirectIncrement(reg) => {
+                self.read(reg);
+                self.write(reg);
+            }
+            // absolute addresses and constants don't use a register
+            Operand::Reg(_) | Operand::Imm(_) | Operand::Absolute(_) => (),
+        }
+    }
+}
+
+fn jump_flags(condition: JmpOpcode) -> Flags {
+    match condition {
+        JmpOpcode::JNE | JmpOpcode::JEQ => Flags::Z,
+        JmpOpcode::JLO | JmpOpcode::JHS => Flags::C,
+        JmpOpcode::JN => Flags::N,
+        JmpOpcode::JGE | JmpOpcode::JL => Flags::N.union(Flags::V),
+        JmpOpcode::JMP => Flags::default(),
+    }
+}
+
+// the flags among some SR bits, for BIC/BIS #bits, SR
+fn status_flags(bits: u16) -> Flags {
+    [(C, Flags::C), (Z, Flags::Z), (N, Flags::N), (V, Flags::V)]
+        .iter()
+        .filter(|&&(bit, _)| bits & bit != 0)
+        .fold(Flags::default(), |flags, &(_, flag)| flags.union(flag))
+}
+
+// MOV @SP+, PC
+fn is_ret(ir: Ir) -> bool {
+    matches!(
+        ir,
+        Ir::Alu {
+            op: AluOp::Mov,
+            src: Operand::IndirectIncrement(SP),
+            dst: Operand::Reg(PC),
+            ..
+        }
+    )
+}
+
+// what an instruction reads and writes, including the SP, PC and SR it touches implicitly
+pub fn def_use(line: &Line) -> DefUse {
+    let mut effect = DefUse::default();
+    match lift(line) {
+        Ir::Invalid => effect.reads = RegSet::ALL, // can't tell, assume the worst
+        Ir::Jump { condition, .. } => {
+            effect.flags_read = jump_flags(condition);
+            effect.write(PC);
+        }
+        ir if is_ret(ir) => {
+            effect.read(SP);
+            effect.write(SP);
+            effect.write(PC);
+            // return values and everything the caller expects to get back
+            effect.reads = effect
+                .reads
+                .union(RegSet::of(&ARGUMENTS))
+                .union(RegSet::of(&CALLEE_SAVED));
+        }
+        // CLRC, EINT and friends only change the bits they name
+        Ir::Alu {
+            op: AluOp::Bic | AluOp::Bis,
+            src: Operand::Imm(bits),
+            dst: Operand::Reg(SR),
+            ..
+        } => {
+            effect.read(SR);
+            effect.writes.insert(SR);
+            effect.flags_written = status_flags(bits);
+        }
+        Ir::Alu { op, src, dst, .. } => {
+            effect.operand(src, true);
+            if op.reads_carry() {
+                effect.flags_read = effect.flags_read.union(Flags::C);
+            }
+            effect.flags_written = op.flags_written();
+            effect.operand(dst, op.reads_destination());
+            if let (Operand::Reg(reg), true) = (dst, op.writes_result()) {
+                effect.write(reg);
+            }
+        }
+        Ir::Push { src, .. } => {
+            effect.operand(src, true);
+            effect.read(SP);
+            effect.write(SP);
+        }
+        Ir::Call { target } => {
+            effect.operand(target, true);
+            effect.reads = effect.reads.union(RegSet::of(&ARGUMENTS));
+            effect.read(SP);
+            effect.write(SP);
+            effect.write(PC);
+            effect.writes = effect.writes.union(RegSet::of(&CALL_CLOBBERED));
+            effect.write(SR); // and whatever the callee left in the flags
+        }
+        Ir::Reti => {
+            effect.reads = RegSet::of(&INTERRUPTED);
+            effect.read(SP);
+            effect.write(SP);
+            effect.write(SR);
+            effect.write(PC);
+        }
+    }
+    effect.reads = effect.reads.without(RegSet::of(&[CG]));
+    effect.writes = effect.writes.without(RegSet::of(&[CG]));
+    effect
+}
+
+// CALL and RET with only `passed` of r12-r15 going across
+fn with_convention(line: &Line, passed: RegSet) -> DefUse {
+    let mut effect = def_use(line);
+    let arguments = RegSet::of(&ARGUMENTS);
+    match lift(line) {
+        Ir::Call { target } => {
+            // CALL r15 still needs r15
+            let mut operand = DefUse::default();
+            operand.operand(target, true);
+            effect.reads = effect
+                .reads
+                .without(arguments)
+                .union(passed)
+                .union(operand.reads);
+        }
+        ir if is_ret(ir) => effect.reads = effect.reads.without(arguments).union(passed),
+        _ => (),
+    }
+    effect
+}
+
+// registers live on entry to every line, assuming the caller wants all of r12-r15 back
+pub fn liveness(lines: &[Line]) -> Vec<RegSet> {
+    live_registers(lines, RegSet::of(&ARGUMENTS))
+}
+
+// a backwards pass over basic blocks, `passed` is which of r12-r15 a CALL hands to
+// the callee and RET hands back, on top of the callee saved registers
+pub fn live_registers(lines: &[Line], passed: RegSet) -> Vec<RegSet> {
+    let by_address = index_by_address(lines);
+    let mut starts: Vec<usize> = block_starts(lines)
+        .into_iter()
+        .filter(|&i| i < lines.len())
+        .collect();
+    starts.sort();
+    let blocks: Vec<(usize, usize)> = starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| (start, starts.get(n + 1).copied().unwrap_or(lines.len())))
+        .collect();
+    let block_of: HashMap<usize, usize> = starts.iter().enumerate().map(|(n, &s)| (s, n)).collect();
+
+    let effects: Vec<DefUse> = lines
+        .iter()
+        .map(|line| with_convention(line, passed))
+        .collect();
+    let reads = |effect: &DefUse| match effect.flags_read {
+        Flags(0) => effect.reads,
+        _ => effect.reads.union(RegSet::of(&[SR])),
+    };
+    // SR only dies when every flag gets overwritten
+    let writes = |effect: &DefUse| match effect.flags_written {
+        Flags::ALL => effect.writes.union(RegSet::of(&[SR])),
+        _ => effect.writes,
+    };
+
+    // anything we can't follow out of a block keeps every register alive
+    let exit = |block: usize| -> Option<Vec<usize>> {
+        let last = blocks[block].1 - 1;
+        let next = successors(lines, last, &by_address);
+        let ir = lift(&lines[last]);
+        let returns = is_ret(ir) || ir == Ir::Reti;
+        let stuck = next.is_empty() && ends_block(&lines[last].instruction) && !returns;
+        match stuck {
+            true => None,
+            false => Some(next.iter().map(|i| block_of[i]).collect()),
+        }
+    };
+    let exits: Vec<Option<Vec<usize>>> = (0..blocks.len()).map(exit).collect();
+
+    let mut live_in = vec![RegSet::default(); lines.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (block, &(start, end)) in blocks.iter().enumerate().rev() {
+            let mut live = match &exits[block] {
+                Some(next) => next.iter().fold(RegSet::default(), |live, &b| {
+                    live.union(live_in[blocks[b].0])
+                }),
+                None => RegSet::ALL,
+            };
+            for i in (start..end).rev() {
+                live = live.without(writes(&effects[i])).union(reads(&effects[i]));
+                if i == start && live != live_in[i] {
+                    changed = true;
+                }
+                live_in[i] = live;
+            }
+        }
+    }
+    live_in
+}
+
+// general purpose registers nothing reads before writing again, safe to use in a hook at lines[index]
+pub fn free_registers(live: &[RegSet], index: usize) -> RegSet {
+    RegSet::of(&INTERRUPTED).without(live[index])
+}
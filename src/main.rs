@@ -1,185 +1,148 @@
-use std::{
-    env::args,
-    fmt::Binary,
-    ops::{Add, ControlFlow},
+use msp430_disassembler::{
+    analyze, annotations::*, data::*, decompile::*, device, device::Device, diff::*, emulator::*,
+    functions::*, gdb, lint::*, listing::*, load_binary, memory::*, options::*, peripherals,
+    peripherals::Peripheral, profile::*, stack::*, stats::*, trace, tui::*,
 };
 
-use bitvec::prelude::*;
-mod globals;
-use globals::*;
-mod pseudo;
-use pseudo::*;
-mod flow;
-use flow::*;
-
 fn main() {
-    let mut word;
-    let path = "./output.bin";
-    let capacity = std::fs::metadata(path).unwrap().len() as usize;
-    let bytes = std::fs::read(path).unwrap();
-    let mut binary_vec = Vec::with_capacity(capacity);
-    binary_vec.push(Word(0));
-
-    for pair in bytes.chunks_exact(2) {
-        word = Word(u16::from_le_bytes([pair[0], pair[1]]));
-        binary_vec.push(word);
+    let options = Options::from_args();
+    let map = load_memory_map(&options);
+    let bytes = load_binary(&options.path);
+    if let Some(problem) = map.check_image(bytes.len()) {
+        eprintln!("warning: {problem}");
     }
-
-    let mut scope = CurrentBinaryScope::new(&binary_vec);
-
-    let mut flowcontroller = FlowController::new();
-
-    loop {
-        scope.step();
-
-        let address = scope.address.0; // stores initial address instead of final address
-                                       // println!("{address:04x}");
-                                       // println!("{:#04x}", scope.current_word.0);
-
-        let flavor = get_instruction_flavor(&scope);
-        let mut instruction = check_special_am(&get_instruction(flavor, &mut scope));
-
-        match check_pseudo(instruction) {
-            Some(pseudo) => instruction = pseudo,
-            _ => (),
+    let base = map.image_base;
+    let device = map.device.as_deref().and_then(device::device);
+
+    let lines = analyze(&bytes, base);
+    if let Some(path) = &options.diff {
+        let new_bytes = load_binary(path);
+        let new_notes = Annotations::load(&sidecar(path)).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+        print_diff(
+            (&lines, &bytes, &load_notes(&options)),
+            (&analyze(&new_bytes, base), &new_bytes, &new_notes),
+            &map,
+        );
+    } else if let Some(steps) = options.step {
+        print_steps(&bytes, base, steps, load_bus(&map, device));
+    } else if let Some(port) = options.gdb {
+        if let Err(e) = gdb::serve(&bytes, base, port, load_bus(&map, device)) {
+            eprintln!("gdb server: {e}");
+            std::process::exit(1);
+        }
+    } else {
+        let notes = load_notes(&options);
+        let starts = notes.function_starts();
+        let mut functions = find_functions_from(&lines, &bytes, base, &starts, map.vectors());
+        notes.apply(&lines, &mut functions);
+        if options.stats {
+            let data = find_data(&lines, &functions, &bytes, base, &notes.data_regions());
+            Stats::collect(&lines, &functions, &data).print();
+        } else if options.stack {
+            print_stack_report(&lines, &functions);
+        } else if options.lint {
+            print_lint(&lines, &functions, &map);
+        } else if options.decompile {
+            print_decompiled(&lines, &functions, device.map_or(&[], |d| d.registers));
+        } else if let Some(steps) = options.profile {
+            let bus = load_bus(&map, device);
+            print_profile(&lines, &functions, &bytes, base, steps, bus);
+        } else if options.tui {
+            let data = find_data(&lines, &functions, &bytes, base, &notes.data_regions());
+            run_tui(&lines, &functions, &data, &bytes, base, &notes);
+        } else {
+            let data = find_data(&lines, &functions, &bytes, base, &notes.data_regions());
+            let trace = load_trace(&options, &map, device, &bytes, base);
+            print_listing(&lines, &functions, &data, &options, trace.as_ref(), &notes);
         }
-
-        // check_for_flow(&mut flowcontroller, &mut scope, instruction);
-
-        println!("{:04x}   {}       {instruction}", address, scope.used_words,);
     }
 }
 
-fn get_instruction(flavor: InstructionFlavor, scope: &mut CurrentBinaryScope) -> Instruction {
-    let word = scope.current_word.0;
-    let bits = word.view_bits::<Lsb0>();
-
-    // println!("{:016b}", &bits[0..=15]);
-
-    use InstructionFlavor::*;
-
-    if flavor == ONE {
-        let opcode = ONE_MAP.get(&bits[7..=9].load()).unwrap();
-        let b = Bbit(bits[6]);
-        let dest_reg = DestReg(bits[0..=3].load());
-        let dam = match dest_reg.0 {
-            SR => ADDRESS_MODE_SR_MAP.get(&bits[4..=5].load::<u8>()).unwrap(),
-            ZR => ADDRESS_MODE_ZERO_MAP
-                .get(&bits[4..=5].load::<u8>())
-                .unwrap(),
-            _ => ADDRESS_MODE_MAP.get(&bits[4..=5].load::<u8>()).unwrap(),
-        };
-        let dest_index = match dam {
-            &AddressMode::IndirectIncrement => {
-                if dest_reg.0 == PC {
-                    Some(Word(scope.get_next().0))
-                } else {
-                    None
-                }
-            }
-            &AddressMode::Indexed => Some(Word(scope.get_next().0.swap_bytes())),
-            _ => None,
-        };
-        return (Instruction::ONE {
-            opcode: *opcode,
-            b: b,
-            dam: *dam,
-            dest: dest_reg,
-            dest_index: dest_index,
-        });
+// --device or --memory-map, moved with --base, --device also names the part a map file is for
+fn load_memory_map(options: &Options) -> MemoryMap {
+    let map = match (&options.memory_map, &options.device) {
+        (Some(path), _) => MemoryMap::load(path),
+        (None, Some(device)) => {
+            MemoryMap::device(device).ok_or(format!("no memory map for {device}"))
+        }
+        (None, None) => Ok(MemoryMap::device(DEFAULT_DEVICE).unwrap()),
+    };
+    let mut map = map.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    if let Some(base) = options.base {
+        map.image_base = base;
     }
+    if let Some(device) = &options.device {
+        map.device = Some(device.clone());
+    }
+    map
+}
 
-    if flavor == TWO {
-        // println!("{:#x}", scope.current_word.0);
-        // println!("{:016b}", &bits[0..=15]);
-
-        let opcode = TWO_MAP.get(&bits[12..=15].load()).unwrap();
-
-        let src_reg = SrcReg(bits[8..=11].load::<u8>().swap_bytes());
-
-        let b = Bbit(bits[6]);
-
-        let dest_reg = DestReg(bits[0..=3].load::<u8>().swap_bytes());
-
-        let sam = match src_reg.0 {
-            SR => ADDRESS_MODE_SR_MAP.get(&bits[4..=5].load::<u8>()).unwrap(),
-            ZR => ADDRESS_MODE_ZERO_MAP
-                .get(&bits[4..=5].load::<u8>())
-                .unwrap(),
-            _ => ADDRESS_MODE_MAP.get(&bits[4..=5].load::<u8>()).unwrap(),
-        };
-
-        let bool_int = match bits[7] {
-            true => 1,
-            false => 0,
-        };
-
-        let dam = match dest_reg.0 {
-            SR => ADDRESS_MODE_SR_MAP.get(&bool_int).unwrap(),
-            ZR => ADDRESS_MODE_ZERO_MAP.get(&bool_int).unwrap(),
-            _ => ADDRESS_MODE_MAP.get(&bool_int).unwrap(),
-        };
+// the selected device's peripherals, for the modes that run the image
+fn load_bus(map: &MemoryMap, device: Option<&Device>) -> Vec<Box<dyn Peripheral>> {
+    let bus = match (device, &map.device) {
+        (Some(device), _) => peripherals::bus(device),
+        (None, Some(name)) => Err(format!("{name}: no register table to emulate it with")),
+        (None, None) => Err(
+            "the memory map doesn't say which device it's for, add a device line or pass --device"
+                .to_owned(),
+        ),
+    };
+    bus.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    })
+}
 
-        let src_index = match sam {
-            &AddressMode::IndirectIncrement => {
-                if src_reg.0 == PC {
-                    Some(Word(scope.get_next().0))
-                } else {
-                    None
-                }
-            }
-            &AddressMode::Indexed | &AddressMode::AbsoluteAddressing => {
-                Some(Word(scope.get_next().0))
-            }
-            _ => None,
-        };
-        let dest_index = match dam {
-            &AddressMode::IndirectIncrement => {
-                if dest_reg.0 == PC {
-                    Some(Word(scope.get_next().0))
-                } else {
-                    None
-                }
-            }
-            &AddressMode::Indexed | &AddressMode::AbsoluteAddressing => {
-                Some(Word(scope.get_next().0))
-            }
-            _ => None,
-        };
-        Instruction::TWO {
-            opcode: *opcode,
-            src: src_reg,
-            dam: *dam,
-            b: b,
-            sam: *sam,
-            dest: dest_reg,
-            src_index: src_index,
-            dest_index: dest_index,
+// the sidecar plus anything from the command line, which gets written back
+fn load_notes(options: &Options) -> Annotations {
+    let path = options
+        .notes
+        .clone()
+        .unwrap_or_else(|| sidecar(&options.path));
+    let mut notes = Annotations::load(&path).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    for (kind, value) in &options.edits {
+        if let Err(e) = notes.edit(kind, value) {
+            eprintln!("{e}");
+            std::process::exit(1);
         }
-    } else {
-        // JMP
-
-        let condition = JUMP_MAP.get(&bits[10..=12].load()).unwrap();
-
-        // dont need to worry about sign extension since bitvec is amazing. Still, thanks for Retr0id and Stuckpixel for helping me out with learning how to do it
-
-        let offset = Offset(((bits[0..=9].load::<i16>()) * 2) + 2); // These are all PC-relative jumps, adding twice the sign-extended offset to the PC, for a jump range of -1024 to +1022 (http://mspgcc.sourceforge.net/manual/x223.html)
-        Instruction::JMP {
-            condition: *condition,
-            offset: offset,
+    }
+    if !options.edits.is_empty() {
+        if let Err(e) = notes.save(&path) {
+            eprintln!("{path}: {e}");
         }
     }
+    notes
 }
 
-fn get_instruction_flavor(scope: &CurrentBinaryScope) -> InstructionFlavor {
-    let word = scope.current_word.0;
-    let bits = word.view_bits::<Lsb0>();
-    use InstructionFlavor::*;
-    if bits[10..16] == bits![0, 0, 1, 0, 0, 0] {
-        ONE
-    } else if bits[13..16] == bits![1, 0, 0] {
-        JMP
-    } else {
-        TWO
+// --trace or --run, saved with --save-trace
+fn load_trace(
+    options: &Options,
+    map: &MemoryMap,
+    device: Option<&Device>,
+    bytes: &[u8],
+    base: u32,
+) -> Option<trace::Trace> {
+    let trace = match (&options.trace, options.run) {
+        (Some(path), _) => trace::Trace::load(path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }),
+        (None, Some(steps)) => trace::Trace::record(bytes, base, steps, load_bus(map, device)),
+        (None, None) => return None,
+    };
+    if let Some(path) = &options.save_trace {
+        if let Err(e) = trace.save(path) {
+            eprintln!("{path}: {e}");
+        }
     }
+    Some(trace)
 }
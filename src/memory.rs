@@ -0,0 +1,347 @@
+use std::{fmt, fs, ops::Range};
+
+use crate::{device, emulator::VECTORS, flow::ADDRESS_LIMIT};
+
+/*
+    Where things live in the address space: peripherals, RAM, info memory,
+    the bootloader, and the flash or FRAM the image is programmed into.
+    main loads one map and hands its image_base to analyze and the
+    emulator, and the map itself to the lint, which checks absolute
+    accesses against the regions here. A part with a different layout
+    only needs a different map.
+
+    Built in maps come from the datasheets, --device picks one. A map file
+    (--memory-map) has one region per line,
+
+        # kind       start    end       access  name
+        peripherals  0x0000   0x0200    rw
+        ram          0x0200   0x0400    rwx
+        info         0x1000   0x1100    rx      info_flash
+        flash        0xc000   0x10000   rx
+        base         0xc000
+        device       msp430g2553
+
+    with end not included, and access and name optional. The image starts
+    at `base`, or the start of the first flash/fram region if there's no
+    base line. --base overrides either. `device` says whose register names
+    and peripheral models (device.rs) go with the map; the built in maps
+    have their own, --device picks one for a map file that doesn't.
+
+    The vectors are the top 32 bytes of the 64K space on every part, the
+    map decides whether there's memory there to hold them.
+
+    Addresses are u32 so FRAM parts with memory above 64K fit. Flash is
+    read only on purpose: writing it needs the flash controller set up
+    first, which is worth a second look every time.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Peripherals,
+    Ram,
+    Info,
+    Bsl,
+    Flash,
+    Fram,
+}
+
+impl Kind {
+    fn parse(word: &str) -> Option<Kind> {
+        Some(match word {
+            "peripherals" => Kind::Peripherals,
+            "ram" => Kind::Ram,
+            "info" => Kind::Info,
+            "bsl" => Kind::Bsl,
+            "flash" => Kind::Flash,
+            "fram" => Kind::Fram,
+            _ => return None,
+        })
+    }
+
+    // what the part allows without setting anything up
+    fn access(self) -> Access {
+        match self {
+            Kind::Peripherals => Access::parse("rw"),
+            Kind::Ram | Kind::Fram => Access::parse("rwx"),
+            Kind::Info | Kind::Bsl | Kind::Flash => Access::parse("rx"),
+        }
+        .unwrap()
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Peripherals => "peripherals",
+            Kind::Ram => "ram",
+            Kind::Info => "info",
+            Kind::Bsl => "bsl",
+            Kind::Flash => "flash",
+            Kind::Fram => "fram",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Access {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Access {
+    pub const READ: Access = Access {
+        read: true,
+        write: false,
+        execute: false,
+    };
+    pub const WRITE: Access = Access {
+        read: false,
+        write: true,
+        execute: false,
+    };
+    pub const EXECUTE: Access = Access {
+        read: false,
+        write: false,
+        execute: true,
+    };
+
+    // "rwx" with any of the letters left out (or a - in their place)
+    pub fn parse(text: &str) -> Option<Access> {
+        let mut access = Access::default();
+        for c in text.chars() {
+            match c {
+                'r' => access.read = true,
+                'w' => access.write = true,
+                'x' => access.execute = true,
+                '-' => (),
+                _ => return None,
+            }
+        }
+        Some(access)
+    }
+
+    fn allows(self, wanted: Access) -> bool {
+        (self.read || !wanted.read)
+            && (self.write || !wanted.write)
+            && (self.execute || !wanted.execute)
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |on: bool, c: char| if on { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.execute, 'x')
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub kind: Kind,
+    pub start: u32,
+    pub end: u32, // not included
+    pub access: Access,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryMap {
+    pub regions: Vec<Region>,
+    pub image_base: u32,
+    pub device: Option<String>, // the part in device.rs this map is for
+}
+
+pub const VECTOR_TABLE: Range<u32> = VECTORS as u32..0x10000;
+
+pub const DEFAULT_DEVICE: &str = "msp430f2274"; // 32 KB of flash at 0x8000, what output.bin is built for
+
+fn region(kind: Kind, start: u32, end: u32) -> Region {
+    Region {
+        name: kind.name().to_owned(),
+        kind,
+        start,
+        end,
+        access: kind.access(),
+    }
+}
+
+// the first page every part has, 8 and 16 bit peripherals and the SFRs
+fn low_peripherals() -> Region {
+    region(Kind::Peripherals, 0x0000, 0x0200)
+}
+
+impl MemoryMap {
+    pub fn device(name: &str) -> Option<MemoryMap> {
+        let regions = match name {
+            "msp430g2553" => vec![
+                low_peripherals(),
+                region(Kind::Ram, 0x0200, 0x0400),
+                region(Kind::Bsl, 0x0c00, 0x1000),
+                region(Kind::Info, 0x1000, 0x1100),
+                region(Kind::Flash, 0xc000, 0x10000),
+            ],
+            "msp430f2274" => vec![
+                low_peripherals(),
+                region(Kind::Ram, 0x0200, 0x0600),
+                region(Kind::Bsl, 0x0c00, 0x1000),
+                region(Kind::Info, 0x1000, 0x1100),
+                region(Kind::Flash, 0x8000, 0x10000),
+            ],
+            "msp430f1611" => vec![
+                low_peripherals(),
+                region(Kind::Bsl, 0x0c00, 0x1000),
+                region(Kind::Info, 0x1000, 0x1100),
+                region(Kind::Ram, 0x1100, 0x3900),
+                region(Kind::Flash, 0x4000, 0x10000),
+            ],
+            "msp430fr5969" => vec![
+                region(Kind::Peripherals, 0x0000, 0x1000),
+                region(Kind::Bsl, 0x1000, 0x1800),
+                region(Kind::Info, 0x1800, 0x1a00),
+                region(Kind::Ram, 0x1c00, 0x2400),
+                region(Kind::Fram, 0x4400, 0x14400),
+            ],
+            _ => return None,
+        };
+        let mut map = MemoryMap {
+            regions,
+            image_base: 0,
+            device: Some(name.to_owned()),
+        };
+        map.image_base = map.main_memory().map_or(0, |r| r.start);
+        Some(map)
+    }
+
+    pub fn parse(text: &str) -> Result<MemoryMap, String> {
+        let mut map = MemoryMap {
+            regions: Vec::new(),
+            image_base: 0,
+            device: None,
+        };
+        let mut base = None;
+        for (n, line) in text.lines().enumerate() {
+            let error = |problem: &str| format!("line {}: {problem}", n + 1);
+            let words: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+            match words[..] {
+                [] => (),
+                ["device", name] => match device::device(name) {
+                    Some(_) => map.device = Some(name.to_owned()),
+                    None => return Err(error("no register table for that device")),
+                },
+                ["base", address] => {
+                    base = Some(parse_address(address).ok_or_else(|| error("bad base address"))?)
+                }
+                [kind, start, end, ref rest @ ..] if rest.len() <= 2 => {
+                    let kind = Kind::parse(kind).ok_or_else(|| error("unknown region kind"))?;
+                    let start = parse_address(start).ok_or_else(|| error("bad start address"))?;
+                    let end = parse_address(end).ok_or_else(|| error("bad end address"))?;
+                    if end <= start {
+                        return Err(error("region ends before it starts"));
+                    }
+                    let access = match rest.first() {
+                        Some(access) => {
+                            Access::parse(access).ok_or_else(|| error("access is some of rwx"))?
+                        }
+                        None => kind.access(),
+                    };
+                    let name = rest.get(1).copied().unwrap_or(kind.name()).to_owned();
+                    map.regions.push(Region {
+                        name,
+                        kind,
+                        start,
+                        end,
+                        access,
+                    });
+                }
+                _ => return Err(error("expected kind start end [access] [name]")),
+            }
+        }
+        map.regions.sort_by_key(|r| r.start);
+        if let Some(pair) = map.regions.windows(2).find(|w| w[0].end > w[1].start) {
+            return Err(format!("{} and {} overlap", pair[0].name, pair[1].name));
+        }
+        map.image_base = match base {
+            Some(base) => base,
+            None => map.main_memory().map_or(0, |r| r.start),
+        };
+        Ok(map)
+    }
+
+    pub fn load(path: &str) -> Result<MemoryMap, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        MemoryMap::parse(&text).map_err(|e| format!("{path}: {e}"))
+    }
+
+    // where images go unless told otherwise
+    fn main_memory(&self) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|r| matches!(r.kind, Kind::Flash | Kind::Fram))
+    }
+
+    // where the vector table is, None if nothing is mapped there
+    pub fn vectors(&self) -> Option<Range<u32>> {
+        let region = self.region(VECTOR_TABLE.start)?;
+        (region.end >= VECTOR_TABLE.end).then_some(VECTOR_TABLE)
+    }
+
+    pub fn region(&self, address: u32) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|r| (r.start..r.end).contains(&address))
+    }
+
+    // why an access isn't allowed, None if it is
+    pub fn check(&self, address: u32, wanted: Access) -> Option<String> {
+        match self.region(address) {
+            None => Some(format!("nothing is mapped at {address:#06x}")),
+            Some(region) if !region.access.allows(wanted) => Some(format!(
+                "{} {address:#06x} in {} ({})",
+                match wanted {
+                    Access::WRITE => "writes",
+                    Access::EXECUTE => "runs code at",
+                    _ => "reads",
+                },
+                region.name,
+                region.access
+            )),
+            Some(_) => None,
+        }
+    }
+
+    // an image of `bytes` bytes at image_base has to fit in whatever region it starts in
+    pub fn check_image(&self, bytes: usize) -> Option<String> {
+        let end = self.image_base as u64 + bytes as u64;
+        match self.region(self.image_base) {
+            None => Some(format!(
+                "the image starts at {:#06x}, where nothing is mapped",
+                self.image_base
+            )),
+            Some(region) if end > region.end as u64 => Some(format!(
+                "the image runs to {end:#06x}, past the end of {} at {:#06x}",
+                region.name, region.end
+            )),
+            Some(_) => None,
+        }
+    }
+}
+
+// the one address syntax, for maps, annotations and --base: 0x hex or decimal, up to 20 bits
+// (an end that isn't included can be right on the limit)
+pub fn parse_address(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let address = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }?;
+    (address <= ADDRESS_LIMIT).then_some(address)
+}
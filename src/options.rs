@@ -0,0 +1,134 @@
+use std::env::args;
+
+use crate::{cycles::Cpu, memory::parse_address};
+
+pub struct Options {
+    pub path: String,
+    pub cycles: bool, // print a cycle column and per block totals
+    pub cpu: Cpu,
+    pub stats: bool,                  // print a summary instead of the listing
+    pub diff: Option<String>,         // newer build to compare the binary against
+    pub stack: bool,                  // per function stack usage instead of the listing
+    pub lint: bool,                   // stack balance and interrupt handler checks
+    pub live: bool,                   // registers live before every line
+    pub decompile: bool,              // rough C instead of the listing
+    pub tui: bool,                    // browse the listing interactively
+    pub step: Option<usize>,          // emulate this many instructions from reset
+    pub gdb: Option<u16>,             // serve the emulator to gdb on this port
+    pub trace: Option<String>,        // execution counts for the listing from a trace file
+    pub run: Option<usize>,           // or from emulating this many instructions
+    pub save_trace: Option<String>,   // where to write the counts back out
+    pub profile: Option<usize>, // emulate this many instructions and say where the cycles went
+    pub notes: Option<String>,  // annotations file, <binary>.notes.toml if not given
+    pub edits: Vec<(String, String)>, // --label and friends, saved into the notes
+    pub device: Option<String>, // built in memory map
+    pub memory_map: Option<String>, // or one from a file
+    pub base: Option<u32>,      // where the image starts, instead of the map's default
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let mut options = Options {
+            path: "./output.bin".to_owned(),
+            cycles: false,
+            cpu: Cpu::MSP430,
+            stats: false,
+            diff: None,
+            stack: false,
+            lint: false,
+            live: false,
+            decompile: false,
+            tui: false,
+            step: None,
+            gdb: None,
+            trace: None,
+            run: None,
+            save_trace: None,
+            profile: None,
+            notes: None,
+            edits: Vec::new(),
+            device: None,
+            memory_map: None,
+            base: None,
+        };
+
+        let mut args = args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--cycles" => options.cycles = true,
+                "--stats" => options.stats = true,
+                "--stack" => options.stack = true,
+                "--lint" => options.lint = true,
+                "--live" => options.live = true,
+                "--decompile" => options.decompile = true,
+                "--tui" => options.tui = true,
+                "--diff" => match args.next() {
+                    Some(path) => options.diff = Some(path),
+                    None => usage("--diff needs the path of the new binary"),
+                },
+                "--step" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(steps) => options.step = Some(steps),
+                    None => usage("--step needs a number of instructions"),
+                },
+                "--gdb" => match args.next().and_then(|port| port.parse().ok()) {
+                    Some(port) => options.gdb = Some(port),
+                    None => usage("--gdb needs a port number"),
+                },
+                "--trace" => match args.next() {
+                    Some(path) => options.trace = Some(path),
+                    None => usage("--trace needs the path of a trace file"),
+                },
+                "--run" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(steps) => options.run = Some(steps),
+                    None => usage("--run needs a number of instructions"),
+                },
+                "--profile" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(steps) => options.profile = Some(steps),
+                    None => usage("--profile needs a number of instructions"),
+                },
+                "--save-trace" => match args.next() {
+                    Some(path) => options.save_trace = Some(path),
+                    None => usage("--save-trace needs a path to write to"),
+                },
+                "--notes" => match args.next() {
+                    Some(path) => options.notes = Some(path),
+                    None => usage("--notes needs the path of an annotations file"),
+                },
+                "--label" | "--comment" | "--signature" | "--code" | "--data" => {
+                    match args.next() {
+                        Some(value) => options.edits.push((arg[2..].to_owned(), value)),
+                        None => usage(&format!("{arg} needs an address and a value")),
+                    }
+                }
+                "--device" => match args.next() {
+                    Some(name) => options.device = Some(name),
+                    None => usage("--device needs a part number, like msp430g2553"),
+                },
+                "--memory-map" => match args.next() {
+                    Some(path) => options.memory_map = Some(path),
+                    None => usage("--memory-map needs the path of a map file"),
+                },
+                "--base" => match args.next().as_deref().and_then(parse_address) {
+                    Some(base) => options.base = Some(base),
+                    None => usage("--base needs an address"),
+                },
+                "--cpu" => {
+                    options.cpu = match args.next().as_deref() {
+                        Some("msp430") => Cpu::MSP430,
+                        Some("msp430x") => Cpu::MSP430X,
+                        other => usage(&format!("unknown cpu {other:?}")),
+                    }
+                }
+                _ if arg.starts_with("--") => usage(&format!("unknown option {arg}")),
+                _ => options.path = arg,
+            }
+        }
+        options
+    }
+}
+
+fn usage(problem: &str) -> ! {
+    eprintln!("{problem}");
+    eprintln!("usage: MSP430_Disassembler [--cycles] [--cpu msp430|msp430x] [--stats] [--stack] [--lint] [--live] [--decompile] [--tui] [--step n] [--gdb port] [--trace file | --run n] [--save-trace file] [--profile n] [--notes file] [--label addr=name] [--comment addr=text] [--signature addr=prototype] [--code start..end] [--data start..end] [--device part] [--memory-map file] [--base addr] [--diff new_binary] [binary]");
+    std::process::exit(1);
+}
@@ -0,0 +1,639 @@
+use std::{
+    io::{stdin, stdout, Read, Write},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use crate::device::{Device, Model};
+
+/*
+    Models of the 2xx peripherals (and the FR parts' eUSCI_A) for the
+    emulator. The registers
+    themselves live in the machine's memory like everything else; a
+    peripheral only gets told when the CPU reads or writes one of its
+    addresses (so it can clear a flag or start sending a byte) and gets
+    ticked with the cycles every instruction took.
+
+    Addresses come from the device table the listing names registers with,
+    so there's one place to fix them, and which models a part gets is listed
+    next to its table. bus() builds them for the selected device; a part
+    without a table or models can't be emulated.
+
+    Host side accesses (gdb's memory reads, Machine::write_byte) skip the
+    hooks. That's how pins get driven: write PxIN from outside and the GPIO
+    model sees the new levels on its next tick.
+
+    Clocks aren't modelled past "MCLK = SMCLK", ACLK runs ACLK_DIVIDER times
+    slower, about what a 32 kHz crystal is next to the 1 MHz DCO.
+*/
+
+pub const ACLK_DIVIDER: u64 = 32;
+
+pub trait Peripheral {
+    // whether the CPU touching this address should call read/write
+    fn owns(&self, address: u16) -> bool;
+    // before the CPU reads an owned address
+    fn read(&mut self, _address: u16, _memory: &mut [u8]) {}
+    // after the CPU wrote an owned address
+    fn write(&mut self, _address: u16, _memory: &mut [u8]) {}
+    fn tick(&mut self, cycles: u64, memory: &mut [u8]);
+    // bit n set means vector n wants service
+    fn requests(&self, _memory: &[u8]) -> u16 {
+        0
+    }
+    // the CPU took vector, clear any flag that goes with it automatically
+    fn acknowledge(&mut self, _vector: u16, _memory: &mut [u8]) {}
+    // power up values, called on every reset
+    fn reset(&mut self, memory: &mut [u8]);
+    // the watchdog's way of pulling the reset line, asking clears it
+    fn wants_reset(&mut self) -> bool {
+        false
+    }
+}
+
+fn word(memory: &[u8], address: u16) -> u16 {
+    u16::from_le_bytes([memory[address as usize], memory[address as usize + 1]])
+}
+
+fn set_word(memory: &mut [u8], address: u16, value: u16) {
+    memory[address as usize..address as usize + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+// everything the device has that the emulator knows about, the UART on stdin/stdout
+pub fn bus(device: &Device) -> Result<Vec<Box<dyn Peripheral>>, String> {
+    if device.models.is_empty() {
+        return Err(format!(
+            "{}: no peripheral models to emulate it with",
+            device.name
+        ));
+    }
+    device
+        .models
+        .iter()
+        .map(|&model| {
+            let peripheral: Option<Box<dyn Peripheral>> = match model {
+                Model::Watchdog { vector } => {
+                    Watchdog::new(device, vector).map(|p| Box::new(p) as _)
+                }
+                Model::TimerA {
+                    prefix,
+                    ccr0_vector,
+                    vector,
+                } => TimerA::new(device, prefix, ccr0_vector, vector).map(|p| Box::new(p) as _),
+                Model::Gpio { ports } => Gpio::new(device, ports).map(|p| Box::new(p) as _),
+                Model::Usci { .. } | Model::Eusci { .. } => {
+                    Uart::stdio(device, model).map(|p| Box::new(p) as _)
+                }
+            };
+            peripheral.ok_or_else(|| {
+                format!(
+                    "{}: the register table is missing something {model:?} needs",
+                    device.name
+                )
+            })
+        })
+        .collect()
+}
+
+// watchdog
+
+const WDTPW: u8 = 0x5a; // what has to be written to the upper byte
+const WDTPW_READ: u8 = 0x69; // and what reads back
+const WDTHOLD: u8 = 0x80;
+const WDTTMSEL: u8 = 0x10; // interval timer instead of reset
+const WDTCNTCL: u8 = 0x08;
+const WDTSSEL: u8 = 0x04; // ACLK
+const WDTIFG: u8 = 0x01; // in IFG1, WDTIE is the same bit of IE1
+
+pub struct Watchdog {
+    ctl: u16,
+    ie1: u16,
+    ifg1: u16,
+    vector: u16,
+    counter: u64,
+    reset_requested: bool,
+}
+
+impl Watchdog {
+    pub fn new(device: &Device, vector: u16) -> Option<Watchdog> {
+        Some(Watchdog {
+            ctl: device.address_of("WDTCTL")?,
+            ie1: device.address_of("IE1")?,
+            ifg1: device.address_of("IFG1")?,
+            vector,
+            counter: 0,
+            reset_requested: false,
+        })
+    }
+
+    fn control(&self, memory: &[u8]) -> u8 {
+        memory[self.ctl as usize]
+    }
+}
+
+impl Peripheral for Watchdog {
+    fn owns(&self, address: u16) -> bool {
+        address & !1 == self.ctl
+    }
+
+    fn write(&mut self, _address: u16, memory: &mut [u8]) {
+        // a wrong password (byte writes included) is a reset, same as on the chip
+        if memory[self.ctl as usize + 1] != WDTPW {
+            memory[self.ifg1 as usize] |= WDTIFG;
+            self.reset_requested = true;
+        }
+        if self.control(memory) & WDTCNTCL != 0 {
+            self.counter = 0;
+        }
+        memory[self.ctl as usize] &= !WDTCNTCL;
+        memory[self.ctl as usize + 1] = WDTPW_READ;
+    }
+
+    fn tick(&mut self, cycles: u64, memory: &mut [u8]) {
+        let control = self.control(memory);
+        if control & WDTHOLD != 0 {
+            return;
+        }
+        let clock = match control & WDTSSEL {
+            0 => 1,
+            _ => ACLK_DIVIDER,
+        };
+        let interval = [32768, 8192, 512, 64][(control & 3) as usize] * clock;
+        self.counter += cycles;
+        if self.counter >= interval {
+            self.counter %= interval;
+            memory[self.ifg1 as usize] |= WDTIFG;
+            if control & WDTTMSEL == 0 {
+                self.reset_requested = true;
+            }
+        }
+    }
+
+    fn requests(&self, memory: &[u8]) -> u16 {
+        let pending = memory[self.ifg1 as usize] & memory[self.ie1 as usize] & WDTIFG;
+        match self.control(memory) & WDTTMSEL != 0 && pending != 0 {
+            true => 1 << self.vector,
+            false => 0,
+        }
+    }
+
+    fn acknowledge(&mut self, vector: u16, memory: &mut [u8]) {
+        if vector == self.vector {
+            memory[self.ifg1 as usize] &= !WDTIFG;
+        }
+    }
+
+    // running in watchdog mode off SMCLK, the reason every main() starts by stopping it
+    fn reset(&mut self, memory: &mut [u8]) {
+        set_word(memory, self.ctl, (WDTPW_READ as u16) << 8);
+        memory[self.ie1 as usize] &= !WDTIFG;
+        self.counter = 0;
+    }
+
+    fn wants_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset_requested)
+    }
+}
+
+// Timer_A3
+
+const TASSEL_ACLK: u16 = 1;
+const TACLR: u16 = 0x0004;
+const TAIE: u16 = 0x0002;
+const TAIFG: u16 = 0x0001;
+const CAP: u16 = 0x0100; // capture mode, nothing to capture from here
+const CCIE: u16 = 0x0010;
+const CCIFG: u16 = 0x0001;
+
+pub struct TimerA {
+    ctl: u16,
+    r: u16,
+    iv: u16,
+    cctl: [u16; 3],
+    ccr: [u16; 3],
+    ccr0_vector: u16,
+    vector: u16,    // CCR1, CCR2 and TAIFG share one, TAxIV says which
+    prescaler: u64, // cycles that haven't made a whole count yet
+    down: bool,     // up/down mode on its way down
+}
+
+impl TimerA {
+    // prefix is what the part calls the timer in register names, TA0 or just TA
+    pub fn new(device: &Device, prefix: &str, ccr0_vector: u16, vector: u16) -> Option<TimerA> {
+        let address = |name: &str| device.address_of(&format!("{prefix}{name}"));
+        let three = |name: &str| -> Option<[u16; 3]> {
+            Some([
+                address(&format!("{name}0"))?,
+                address(&format!("{name}1"))?,
+                address(&format!("{name}2"))?,
+            ])
+        };
+        Some(TimerA {
+            ctl: address("CTL")?,
+            r: address("R")?,
+            iv: address("IV")?,
+            cctl: three("CCTL")?,
+            ccr: three("CCR")?,
+            ccr0_vector,
+            vector,
+            prescaler: 0,
+            down: false,
+        })
+    }
+
+    // one count of TAR in the given mode control, returns whether it rolled over to 0
+    fn count(&mut self, mode: u16, memory: &mut [u8]) -> bool {
+        let r = word(memory, self.r);
+        let ccr0 = word(memory, self.ccr[0]);
+        let (next, rolled) = match mode {
+            // up, 0 to CCR0
+            1 if r >= ccr0 => (0, true),
+            1 => (r + 1, false),
+            // continuous, 0 to 0xffff
+            2 => (r.wrapping_add(1), r == 0xffff),
+            // up/down, 0 to CCR0 and back, TAIFG on the way from 1 to 0
+            _ if self.down => {
+                self.down = r > 1;
+                (r.saturating_sub(1), r == 1)
+            }
+            _ if r >= ccr0 => {
+                self.down = true;
+                (r.saturating_sub(1), false)
+            }
+            _ => (r + 1, false),
+        };
+        set_word(memory, self.r, next);
+        for (&cctl, &ccr) in self.cctl.iter().zip(&self.ccr) {
+            let control = word(memory, cctl);
+            if control & CAP == 0 && next == word(memory, ccr) {
+                set_word(memory, cctl, control | CCIFG);
+            }
+        }
+        rolled
+    }
+
+    // highest priority of the interrupts TAxIV reports, 0 if none
+    fn vector_source(&self, memory: &[u8]) -> u16 {
+        let enabled = |control: u16| control & CCIE != 0 && control & CCIFG != 0;
+        if enabled(word(memory, self.cctl[1])) {
+            2
+        } else if enabled(word(memory, self.cctl[2])) {
+            4
+        } else if word(memory, self.ctl) & (TAIE | TAIFG) == TAIE | TAIFG {
+            10
+        } else {
+            0
+        }
+    }
+}
+
+impl Peripheral for TimerA {
+    fn owns(&self, address: u16) -> bool {
+        let address = address & !1;
+        address == self.iv || address == self.ctl
+    }
+
+    // reading TAxIV hands out the top source and clears its flag
+    fn read(&mut self, address: u16, memory: &mut [u8]) {
+        if address & !1 != self.iv {
+            return;
+        }
+        let source = self.vector_source(memory);
+        set_word(memory, self.iv, source);
+        let (register, flag) = match source {
+            2 => (self.cctl[1], CCIFG),
+            4 => (self.cctl[2], CCIFG),
+            10 => (self.ctl, TAIFG),
+            _ => return,
+        };
+        set_word(memory, register, word(memory, register) & !flag);
+    }
+
+    fn write(&mut self, address: u16, memory: &mut [u8]) {
+        let control = word(memory, self.ctl);
+        if address & !1 == self.ctl && control & TACLR != 0 {
+            set_word(memory, self.r, 0);
+            set_word(memory, self.ctl, control & !TACLR);
+            self.prescaler = 0;
+            self.down = false;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64, memory: &mut [u8]) {
+        let control = word(memory, self.ctl);
+        let mode = control >> 4 & 3;
+        // stopped, or up mode with nothing to count to
+        if mode == 0 || (mode == 1 && word(memory, self.ccr[0]) == 0) {
+            return;
+        }
+        let clock = match control >> 8 & 3 {
+            TASSEL_ACLK => ACLK_DIVIDER,
+            _ => 1,
+        };
+        let divider = clock << (control >> 6 & 3);
+        self.prescaler += cycles;
+        for _ in 0..self.prescaler / divider {
+            if self.count(mode, memory) {
+                set_word(memory, self.ctl, word(memory, self.ctl) | TAIFG);
+            }
+        }
+        self.prescaler %= divider;
+    }
+
+    fn requests(&self, memory: &[u8]) -> u16 {
+        let control = word(memory, self.cctl[0]);
+        let ccr0 = match control & (CCIE | CCIFG) == CCIE | CCIFG {
+            true => 1 << self.ccr0_vector,
+            false => 0,
+        };
+        let shared = match self.vector_source(memory) {
+            0 => 0,
+            _ => 1 << self.vector,
+        };
+        ccr0 | shared
+    }
+
+    // only CCR0's flag clears itself, the rest wait for TAxIV to be read
+    fn acknowledge(&mut self, vector: u16, memory: &mut [u8]) {
+        if vector == self.ccr0_vector {
+            let control = word(memory, self.cctl[0]);
+            set_word(memory, self.cctl[0], control & !CCIFG);
+        }
+    }
+
+    fn reset(&mut self, memory: &mut [u8]) {
+        for register in [self.ctl, self.r, self.iv]
+            .iter()
+            .chain(&self.cctl)
+            .chain(&self.ccr)
+        {
+            set_word(memory, *register, 0);
+        }
+        self.prescaler = 0;
+        self.down = false;
+    }
+}
+
+// 2xx ports, each PxIN with the vector for the port
+
+struct Port {
+    input: u16, // PxIN, PxOUT etc. follow it in this order
+    vector: u16,
+    pins: u8, // levels as of the last tick
+}
+
+impl Port {
+    fn register(&self, offset: u16) -> usize {
+        (self.input + offset) as usize
+    }
+}
+
+const OUT: u16 = 1;
+const DIR: u16 = 2;
+const IFG: u16 = 3;
+const IES: u16 = 4; // 1 for the falling edge
+const IE: u16 = 5;
+const SEL: u16 = 6;
+const REN: u16 = 7;
+
+pub struct Gpio {
+    ports: Vec<Port>,
+}
+
+impl Gpio {
+    pub fn new(device: &Device, ports: &[(&str, u16)]) -> Option<Gpio> {
+        let ports = ports.iter().map(|&(input, vector)| {
+            Some(Port {
+                input: device.address_of(input)?,
+                vector,
+                pins: 0,
+            })
+        });
+        Some(Gpio {
+            ports: ports.collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl Peripheral for Gpio {
+    fn owns(&self, address: u16) -> bool {
+        self.ports.iter().any(|port| port.input == address)
+    }
+
+    // PxIN is read only to the CPU
+    fn write(&mut self, address: u16, memory: &mut [u8]) {
+        for port in self.ports.iter().filter(|port| port.input == address) {
+            memory[port.input as usize] = port.pins;
+        }
+    }
+
+    // outputs read back what they drive, inputs whatever was last written from outside
+    fn tick(&mut self, _cycles: u64, memory: &mut [u8]) {
+        for port in &mut self.ports {
+            let direction = memory[port.register(DIR)];
+            let level =
+                (memory[port.register(0)] & !direction) | (memory[port.register(OUT)] & direction);
+            memory[port.register(0)] = level;
+
+            let changed = level ^ port.pins;
+            let falling = memory[port.register(IES)];
+            memory[port.register(IFG)] |= changed & ((level & !falling) | (!level & falling));
+            port.pins = level;
+        }
+    }
+
+    fn requests(&self, memory: &[u8]) -> u16 {
+        self.ports
+            .iter()
+            .filter(|port| memory[port.register(IFG)] & memory[port.register(IE)] != 0)
+            .fold(0, |requests, port| requests | 1 << port.vector)
+    }
+
+    fn reset(&mut self, memory: &mut [u8]) {
+        for port in &mut self.ports {
+            for offset in [DIR, IFG, IE, SEL, REN] {
+                memory[port.register(offset)] = 0;
+            }
+            port.pins = memory[port.register(0)];
+        }
+    }
+}
+
+// USCI_A0 or eUSCI_A0 in UART mode, same flag bits, they differ in where the flags live
+
+const UCRXIFG: u8 = 0x01; // in IFG2 or UCA0IFG, the enables are the same bits of IE2 or UCA0IE
+const UCTXIFG: u8 = 0x02;
+
+pub struct Uart {
+    ie: u16,
+    ifg: u16,
+    br0: u16,
+    br1: u16,
+    rxbuf: u16,
+    txbuf: u16,
+    iv: Option<u16>, // UCA0IV, eUSCI only
+    rx_vector: u16,
+    tx_vector: u16,
+    input: Receiver<u8>,
+    output: Sender<u8>,
+    sending: Option<(u8, u64)>, // byte in the shift register and the cycles left on it
+    receive_wait: u64,          // cycles until the next byte can arrive
+}
+
+impl Uart {
+    // model is the device's Usci or Eusci entry, anything else isn't a UART
+    pub fn new(
+        device: &Device,
+        model: Model,
+        input: Receiver<u8>,
+        output: Sender<u8>,
+    ) -> Option<Uart> {
+        let address = |name| device.address_of(name);
+        let (ie, ifg, br0, br1, iv, rx_vector, tx_vector) = match model {
+            Model::Usci {
+                rx_vector,
+                tx_vector,
+            } => (
+                address("IE2")?,
+                address("IFG2")?,
+                address("UCA0BR0")?,
+                address("UCA0BR1")?,
+                None,
+                rx_vector,
+                tx_vector,
+            ),
+            Model::Eusci { vector } => {
+                let brw = address("UCA0BRW")?;
+                (
+                    address("UCA0IE")?,
+                    address("UCA0IFG")?,
+                    brw,
+                    brw + 1,
+                    Some(address("UCA0IV")?),
+                    vector,
+                    vector,
+                )
+            }
+            _ => return None,
+        };
+        Some(Uart {
+            ie,
+            ifg,
+            br0,
+            br1,
+            rxbuf: address("UCA0RXBUF")?,
+            txbuf: address("UCA0TXBUF")?,
+            iv,
+            rx_vector,
+            tx_vector,
+            input,
+            output,
+            sending: None,
+            receive_wait: 0,
+        })
+    }
+
+    // a thread each way so the emulator never blocks on the terminal
+    pub fn stdio(device: &Device, model: Model) -> Option<Uart> {
+        let (to_uart, input) = channel();
+        let (output, from_uart) = channel::<u8>();
+        let uart = Uart::new(device, model, input, output)?;
+        thread::spawn(move || {
+            for byte in stdin().lock().bytes().map_while(Result::ok) {
+                if to_uart.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || {
+            for byte in from_uart {
+                let mut stdout = stdout();
+                let _ = stdout.write_all(&[byte]).and_then(|_| stdout.flush());
+            }
+        });
+        Some(uart)
+    }
+
+    // start bit, 8 data bits and a stop bit, BRCLK taken to be SMCLK
+    fn frame_cycles(&self, memory: &[u8]) -> u64 {
+        let divider = u16::from_le_bytes([memory[self.br0 as usize], memory[self.br1 as usize]]);
+        10 * divider.max(1) as u64
+    }
+}
+
+impl Peripheral for Uart {
+    fn owns(&self, address: u16) -> bool {
+        address == self.rxbuf || address == self.txbuf || Some(address & !1) == self.iv
+    }
+
+    // reading UCA0IV hands out the top source, RX before TX, and clears its flag
+    fn read(&mut self, address: u16, memory: &mut [u8]) {
+        if address == self.rxbuf {
+            memory[self.ifg as usize] &= !UCRXIFG;
+        }
+        if let Some(iv) = self.iv.filter(|&iv| iv == address & !1) {
+            let pending = memory[self.ifg as usize] & memory[self.ie as usize];
+            let (source, flag) = if pending & UCRXIFG != 0 {
+                (2, UCRXIFG)
+            } else if pending & UCTXIFG != 0 {
+                (4, UCTXIFG)
+            } else {
+                (0, 0)
+            };
+            set_word(memory, iv, source);
+            memory[self.ifg as usize] &= !flag;
+        }
+    }
+
+    fn write(&mut self, address: u16, memory: &mut [u8]) {
+        if address == self.txbuf {
+            memory[self.ifg as usize] &= !UCTXIFG;
+            self.sending = Some((memory[self.txbuf as usize], self.frame_cycles(memory)));
+        }
+    }
+
+    fn tick(&mut self, cycles: u64, memory: &mut [u8]) {
+        match self.sending {
+            Some((byte, left)) if left <= cycles => {
+                let _ = self.output.send(byte); // nobody listening is fine
+                self.sending = None;
+                memory[self.ifg as usize] |= UCTXIFG;
+            }
+            Some((byte, left)) => self.sending = Some((byte, left - cycles)),
+            None => (),
+        }
+
+        // the next byte waits until the last one has been read out of RXBUF
+        self.receive_wait = self.receive_wait.saturating_sub(cycles);
+        if self.receive_wait == 0 && memory[self.ifg as usize] & UCRXIFG == 0 {
+            if let Ok(byte) = self.input.try_recv() {
+                memory[self.rxbuf as usize] = byte;
+                memory[self.ifg as usize] |= UCRXIFG;
+                self.receive_wait = self.frame_cycles(memory);
+            }
+        }
+    }
+
+    fn requests(&self, memory: &[u8]) -> u16 {
+        let pending = memory[self.ifg as usize] & memory[self.ie as usize];
+        let mut requests = 0;
+        if pending & UCRXIFG != 0 {
+            requests |= 1 << self.rx_vector;
+        }
+        if pending & UCTXIFG != 0 {
+            requests |= 1 << self.tx_vector;
+        }
+        requests
+    }
+
+    // TXIFG comes up set, there's nothing in the way of sending
+    fn reset(&mut self, memory: &mut [u8]) {
+        if let Some(iv) = self.iv {
+            set_word(memory, iv, 0);
+        }
+        memory[self.ie as usize] &= !(UCRXIFG | UCTXIFG);
+        memory[self.ifg as usize] = memory[self.ifg as usize] & !UCRXIFG | UCTXIFG;
+        self.sending = None;
+        self.receive_wait = 0;
+    }
+}
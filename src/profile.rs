@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use crate::{
+    emulator::{Machine, Step, Stepped},
+    flow::block_starts,
+    functions::Function,
+    globals::{Instruction, Line, OneOpcode, SP},
+    peripherals::Peripheral,
+};
+
+/*
+    Cycles spent per instruction, block and function over an emulated run,
+    counted with the same tables as --cycles.
+
+    Functions are tracked with a shadow call stack: a CALL or an interrupt
+    pushes a frame holding the SP it left behind, and a frame is gone once
+    SP climbs back above that (RET, RETI, or anything else that unwinds).
+    Interrupt entry costs go to the ISR. Time with the CPU off isn't
+    anybody's, it's counted on its own since that's what the battery wants
+    to see.
+*/
+
+const TOP: usize = 20; // rows in each table
+
+#[derive(Clone, Copy, Default)]
+pub struct Cost {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct FunctionCost {
+    pub calls: u64,
+    pub own: u64,       // cycles in the function itself
+    pub inclusive: u64, // and in everything it called
+}
+
+struct Frame {
+    function: u32, // entry address
+    sp: u16,       // SP right after the call pushed its return address
+}
+
+#[derive(Default)]
+pub struct Profile {
+    pub instructions: HashMap<u32, Cost>,
+    pub functions: HashMap<u32, FunctionCost>,
+    pub calls: HashMap<(u32, u32), Cost>, // caller -> callee, cycles inclusive
+    pub asleep: u64,
+    pub total: u64,
+}
+
+impl Profile {
+    // run from reset for `steps` instructions, sleeping doesn't use any up
+    pub fn record(
+        bytes: &[u8],
+        base: u32,
+        steps: usize,
+        peripherals: Vec<Box<dyn Peripheral>>,
+    ) -> Profile {
+        let mut machine = Machine::with_peripherals(peripherals);
+        machine.load_image(bytes, base);
+        machine.reset();
+
+        let mut profile = Profile::default();
+        let mut stack = vec![Frame {
+            function: machine.pc().into(),
+            sp: u16::MAX, // whatever main does to SP, it doesn't return
+        }];
+        profile
+            .functions
+            .entry(machine.pc().into())
+            .or_default()
+            .calls += 1;
+
+        machine.run(steps, |machine, stepped| {
+            let Stepped {
+                pc,
+                line,
+                cycles,
+                step,
+                ..
+            } = stepped;
+            profile.total += cycles;
+
+            match step {
+                Step::Sleeping => {
+                    profile.asleep += cycles;
+                    return;
+                }
+                // the chip starts over in the reset handler, that's a call like the first one
+                Step::Reset => {
+                    stack.truncate(1);
+                    stack[0].function = machine.pc().into();
+                    profile
+                        .functions
+                        .entry(machine.pc().into())
+                        .or_default()
+                        .calls += 1;
+                }
+                Step::Interrupt => profile.enter(&mut stack, machine.pc().into(), machine),
+                Step::Ran => (),
+            }
+
+            // interrupt entry belongs to the ISR's first instruction
+            let at = match step {
+                Step::Interrupt => machine.pc(),
+                _ => pc,
+            };
+            let cost = profile.instructions.entry(at.into()).or_default();
+            cost.count += (step != Step::Interrupt) as u64;
+            cost.cycles += cycles;
+            profile.charge(&stack, cycles);
+
+            let sp = machine.regs[SP as usize];
+            while stack.len() > 1 && sp > stack[stack.len() - 1].sp {
+                stack.pop();
+            }
+            let calls = matches!(
+                line.raw,
+                Instruction::ONE {
+                    opcode: OneOpcode::CALL,
+                    ..
+                }
+            );
+            if calls && step == Step::Ran {
+                profile.enter(&mut stack, machine.pc().into(), machine);
+            }
+        });
+        profile
+    }
+
+    fn enter(&mut self, stack: &mut Vec<Frame>, function: u32, machine: &Machine) {
+        let caller = stack[stack.len() - 1].function;
+        self.functions.entry(function).or_default().calls += 1;
+        self.calls.entry((caller, function)).or_default().count += 1;
+        stack.push(Frame {
+            function,
+            sp: machine.regs[SP as usize],
+        });
+    }
+
+    // own time to the top frame, inclusive to everything on the stack once each
+    fn charge(&mut self, stack: &[Frame], cycles: u64) {
+        for (depth, frame) in stack.iter().enumerate() {
+            let recursive = stack[..depth].iter().any(|f| f.function == frame.function);
+            let cost = self.functions.entry(frame.function).or_default();
+            if !recursive {
+                cost.inclusive += cycles;
+            }
+            if depth == stack.len() - 1 {
+                cost.own += cycles;
+            }
+            if depth > 0 {
+                let caller = stack[depth - 1].function;
+                self.calls
+                    .entry((caller, frame.function))
+                    .or_default()
+                    .cycles += cycles;
+            }
+        }
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    part as f64 * 100.0 / total.max(1) as f64
+}
+
+// --profile: flat profile, hot blocks and instructions, then who called whom
+pub fn print_profile(
+    lines: &[Line],
+    functions: &[Function],
+    bytes: &[u8],
+    base: u32,
+    steps: usize,
+    peripherals: Vec<Box<dyn Peripheral>>,
+) {
+    let profile = Profile::record(bytes, base, steps, peripherals);
+    let cpu = |i: usize| lines[i].address.0;
+    let names: HashMap<u32, String> = functions
+        .iter()
+        .map(|f| (cpu(f.start), f.name(lines)))
+        .collect();
+    let name = |address: &u32| match names.get(address) {
+        Some(name) => name.clone(),
+        None => format!("sub_{address:04x}"),
+    };
+    let total = profile.total;
+
+    println!(
+        "{total} cycles, {} ({:.1}%) asleep",
+        profile.asleep,
+        percent(profile.asleep, total)
+    );
+
+    println!();
+    println!("  own%       own  inclusive    calls  function");
+    let mut flat: Vec<(&u32, &FunctionCost)> = profile.functions.iter().collect();
+    flat.sort_by_key(|(address, cost)| (std::cmp::Reverse(cost.own), **address));
+    for (address, cost) in flat {
+        println!(
+            "{:>5.1}% {:>9} {:>10} {:>8}  {}",
+            percent(cost.own, total),
+            cost.own,
+            cost.inclusive,
+            cost.calls,
+            name(address)
+        );
+    }
+
+    // blocks from the static analysis plus function entries, an instruction belongs to the last start before it
+    let mut starts: Vec<u32> = block_starts(lines)
+        .into_iter()
+        .chain(functions.iter().map(|f| f.start))
+        .filter(|&i| i < lines.len())
+        .map(cpu)
+        .collect();
+    starts.sort();
+    let mut blocks: HashMap<u32, Cost> = HashMap::new();
+    for (&pc, cost) in &profile.instructions {
+        let start = match starts.partition_point(|&s| s <= pc) {
+            0 => pc,
+            n => starts[n - 1],
+        };
+        let block = blocks.entry(start).or_default();
+        block.cycles += cost.cycles;
+        block.count = block.count.max(cost.count);
+    }
+
+    for (title, costs) in [("block", blocks), ("instruction", profile.instructions)] {
+        println!();
+        println!("  cycles%    cycles     count  {title}");
+        let mut hot: Vec<(u32, Cost)> = costs.into_iter().collect();
+        hot.sort_by_key(|(address, cost)| (std::cmp::Reverse(cost.cycles), *address));
+        for (address, cost) in hot.into_iter().take(TOP) {
+            println!(
+                "{:>8.1}% {:>9} {:>9}  {address:04x}",
+                percent(cost.cycles, total),
+                cost.cycles,
+                cost.count
+            );
+        }
+    }
+
+    println!();
+    println!("call graph");
+    let mut callers: Vec<&u32> = profile.functions.keys().collect();
+    callers.sort();
+    for caller in callers {
+        let mut callees: Vec<(&(u32, u32), &Cost)> = profile
+            .calls
+            .iter()
+            .filter(|((from, _), _)| from == caller)
+            .collect();
+        if callees.is_empty() {
+            continue;
+        }
+        callees.sort_by_key(|(edge, cost)| (std::cmp::Reverse(cost.cycles), **edge));
+        println!("{}", name(caller));
+        for ((_, callee), cost) in callees {
+            println!(
+                "    {:<24} {:>6} calls {:>10} cycles",
+                name(callee),
+                cost.count,
+                cost.cycles
+            );
+        }
+    }
+}
@@ -1,6 +1,4 @@
-use core::fmt;
-
-use crate::globals::{AddressMode, Bbit, DestReg, Instruction, TwoOpcode, PC, SP, SR};
+use crate::globals::{AddressMode, DestReg, Instruction, TwoOpcode, PC, SP, SR};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PsuedoOpcode {
@@ -56,20 +54,20 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                 MOV => {
                     if src.0 == PC && sam == IndirectIncrement && src_index.unwrap().0 == 0 {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
+                            dest_index,
                             opcode: CLR,
-                            dam: dam,
-                            b: b,
+                            dam,
+                            b,
                             dest: Some(dest),
                         });
                     }
 
                     if dest.0 == src.0 && sam == Direct && dam == Direct {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
+                            dest_index,
                             opcode: NOP,
-                            dam: dam,
-                            b: b,
+                            dam,
+                            b,
                             dest: None,
                         });
                     }
@@ -77,27 +75,28 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                         if dest.0 == PC {
                             return Some(PSEUDO {
                                 opcode: RET,
-                                dam: dam,
-                                b: b,
+                                dam,
+                                b,
                                 dest: None,
-                                dest_index: dest_index,
+                                dest_index,
                             });
                         } else {
                             return Some(PSEUDO {
-                                dest_index: dest_index,
+                                dest_index,
                                 opcode: POP,
-                                dam: dam,
-                                b: b,
+                                dam,
+                                b,
                                 dest: Some(dest),
                             });
                         }
                     }
-                    if dest.0 == PC && dam == Direct {
+                    // BR only carries a register, MOV #addr, PC and MOV tbl(rN), PC stay as they are
+                    if dest.0 == PC && dam == Direct && sam == Direct {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
+                            dest_index,
                             opcode: BR,
-                            dam: dam,
-                            b: b,
+                            dam,
+                            b,
                             dest: Some(DestReg(src.0)),
                         });
                     }
@@ -109,37 +108,37 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                         match src_index.unwrap().0 {
                             1 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: CLRC,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
                             2 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: CLRZ,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
                             4 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
+                                    dest_index,
                                     opcode: CLRN,
-                                    dam: dam,
-                                    b: b,
+                                    dam,
+                                    b,
                                     dest: None,
                                 })
                             }
                             8 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: DINT,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
@@ -154,37 +153,37 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                         match src_index.unwrap().0 {
                             1 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: SETC,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
                             2 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: SETZ,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
                             4 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: SETN,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
                             8 => {
                                 return Some(PSEUDO {
-                                    dest_index: dest_index,
-                                    dam: dam,
+                                    dest_index,
+                                    dam,
                                     opcode: EINT,
-                                    b: b,
+                                    b,
                                     dest: None,
                                 })
                             }
@@ -196,10 +195,19 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                 ADD => {
                     if dest.0 == src.0 {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
-                            dam: dam,
+                            dest_index,
+                            dam,
                             opcode: RLA,
-                            b: b,
+                            b,
+                            dest: Some(dest),
+                        });
+                    }
+                    if src.0 == PC && sam == IndirectIncrement && src_index.unwrap().0 == 1 {
+                        return Some(PSEUDO {
+                            dest_index,
+                            dam,
+                            opcode: INC,
+                            b,
                             dest: Some(dest),
                         });
                     }
@@ -208,10 +216,10 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                 ADDC => {
                     if dest.0 == src.0 {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
-                            dam: dam,
-                            opcode: RLA,
-                            b: b,
+                            dest_index,
+                            dam,
+                            opcode: RLC,
+                            b,
                             dest: Some(dest),
                         });
                     }
@@ -227,10 +235,10 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                 CMP => {
                     if src.0 == PC && sam == IndirectIncrement && src_index.unwrap().0 == 0 {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
-                            dam: dam,
+                            dest_index,
+                            dam,
                             opcode: TST,
-                            b: b,
+                            b,
                             dest: Some(dest),
                         });
                     }
@@ -239,22 +247,10 @@ pub fn check_pseudo(instruction: Instruction) -> Option<Instruction> {
                 SUB => {
                     if src.0 == PC && sam == IndirectIncrement && src_index.unwrap().0 == 1 {
                         return Some(PSEUDO {
-                            dest_index: dest_index,
-                            dam: dam,
+                            dest_index,
+                            dam,
                             opcode: DEC,
-                            b: b,
-                            dest: Some(dest),
-                        });
-                    }
-                    None
-                }
-                ADD => {
-                    if src.0 == PC && sam == IndirectIncrement && src_index.unwrap().0 == 1 {
-                        return Some(PSEUDO {
-                            dest_index: dest_index,
-                            dam: dam,
-                            opcode: INC,
-                            b: b,
+                            b,
                             dest: Some(dest),
                         });
                     }
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use crate::{
+    flow::{call_target, index_by_address, successors, Address},
+    functions::{Function, FunctionKind},
+    globals::{Line, SP, SR},
+    ir::{lift, AluOp, Ir, Operand, GIE},
+};
+
+/*
+    Depths are bytes below the stack pointer the function was entered with,
+    so the return address (or the PC/SR pair of an interrupt) isn't counted
+    in the frame, the caller pays for it.
+*/
+
+pub const INTERRUPT_ENTRY: u32 = 4; // PC and SR
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpEffect {
+    Delta(i32), // bytes pushed, negative for pops
+    Unknown,    // SP loaded from somewhere we can't follow
+}
+
+// PUSHM and POPM are MSP430X only, so the decoder leaves them Invalid
+fn pushm_popm(opcode: u16) -> Option<i32> {
+    let count = ((opcode >> 4) & 0xf) as i32 + 1;
+    match opcode & 0xff00 {
+        0x1400 => Some(4 * count),  // PUSHM.A
+        0x1500 => Some(2 * count),  // PUSHM.W
+        0x1600 => Some(-4 * count), // POPM.A
+        0x1700 => Some(-2 * count), // POPM.W
+        _ => None,
+    }
+}
+
+pub fn sp_effect(line: &Line) -> SpEffect {
+    use SpEffect::*;
+    match lift(line) {
+        Ir::Invalid => Delta(pushm_popm(line.words.0[0].0).unwrap_or(0)),
+        Ir::Push { .. } => Delta(2),
+        Ir::Reti => Delta(-(INTERRUPT_ENTRY as i32)),
+        Ir::Alu { op, src, dst, .. } => {
+            // @SP+ pops, POP and RET are both MOV @SP+, x
+            let popped = match src {
+                Operand::IndirectIncrement(SP) => -2,
+                _ => 0,
+            };
+            if dst != Operand::Reg(SP) || !op.writes_result() {
+                return Delta(popped);
+            }
+            // INC, DEC and ADD #-1 are all an immediate too
+            match (op, src) {
+                (AluOp::Sub, Operand::Imm(n)) => Delta(n as i16 as i32),
+                (AluOp::Add, Operand::Imm(n)) => Delta(-(n as i16 as i32)),
+                _ => Unknown,
+            }
+        }
+        _ => Delta(0),
+    }
+}
+
+pub struct StackInfo {
+    pub depth: HashMap<usize, i32>, // depth before every line of the body
+    pub frame: u32,                 // deepest the function itself goes
+    pub mismatches: Vec<usize>,     // lines reached with two different depths
+    pub unknown: Vec<usize>,        // lines that load SP with something we can't follow
+    pub calls: Vec<(usize, Option<Address>)>, // CALL lines and their target, None if indirect
+}
+
+pub fn analyze_function(lines: &[Line], function: &Function) -> StackInfo {
+    let by_address = index_by_address(lines);
+    let mut info = StackInfo {
+        depth: HashMap::new(),
+        frame: 0,
+        mismatches: Vec::new(),
+        unknown: Vec::new(),
+        calls: Vec::new(),
+    };
+
+    let mut queue = vec![(function.start, 0)];
+    while let Some((i, depth)) = queue.pop() {
+        if function.body.binary_search(&i).is_err() {
+            continue; // tail call into another function
+        }
+        match info.depth.get(&i) {
+            Some(&seen) if seen != depth => {
+                if !info.mismatches.contains(&i) {
+                    info.mismatches.push(i);
+                }
+                continue;
+            }
+            Some(_) => continue,
+            None => info.depth.insert(i, depth),
+        };
+        info.frame = info.frame.max(depth.max(0) as u32);
+
+        if let Ir::Call { .. } = lift(&lines[i]) {
+            info.calls.push((i, call_target(&lines[i])));
+        }
+
+        let after = match sp_effect(&lines[i]) {
+            SpEffect::Delta(delta) => depth + delta,
+            SpEffect::Unknown => {
+                info.unknown.push(i);
+                depth
+            }
+        };
+        info.frame = info.frame.max(after.max(0) as u32);
+        for next in successors(lines, i, &by_address) {
+            queue.push((next, after));
+        }
+    }
+    info.calls.sort();
+    info.mismatches.sort();
+    info
+}
+
+// worst case depth of a function including everything it calls, None when recursive
+pub fn worst_case(
+    lines: &[Line],
+    index: usize,
+    functions: &[Function],
+    infos: &[StackInfo],
+    visiting: &mut Vec<usize>,
+    memo: &mut HashMap<usize, Option<u32>>,
+) -> Option<u32> {
+    if let Some(&known) = memo.get(&index) {
+        return known;
+    }
+    if visiting.contains(&index) {
+        return None;
+    }
+    visiting.push(index);
+
+    let info = &infos[index];
+    let mut worst = Some(info.frame);
+    for &(line, target) in &info.calls {
+        let callee = target.and_then(|target| {
+            functions
+                .iter()
+                .position(|f| lines[f.start].address == target)
+        });
+        let Some(callee) = callee else { continue }; // indirect calls are reported separately
+        let below = worst_case(lines, callee, functions, infos, visiting, memo);
+        let Some(&depth) = info.depth.get(&line) else {
+            continue;
+        };
+        let at_call = depth.max(0) as u32 + 2;
+        worst = match (worst, below) {
+            (Some(worst), Some(below)) => Some(worst.max(at_call + below)),
+            _ => None,
+        };
+    }
+
+    visiting.pop();
+    memo.insert(index, worst);
+    worst
+}
+
+fn enables_interrupts(lines: &[Line], function: &Function) -> bool {
+    function.body.iter().any(|&i| {
+        matches!(
+            lift(&lines[i]),
+            Ir::Alu {
+                op: AluOp::Bis,
+                src: Operand::Imm(GIE),
+                dst: Operand::Reg(SR),
+                ..
+            }
+        )
+    })
+}
+
+fn print_depth(depth: Option<u32>) -> String {
+    match depth {
+        Some(depth) => format!("{depth} bytes"),
+        None => "unbounded (recursive)".to_owned(),
+    }
+}
+
+pub fn print_stack_report(lines: &[Line], functions: &[Function]) {
+    if functions.is_empty() {
+        println!("no functions found");
+        return;
+    }
+    let infos: Vec<StackInfo> = functions
+        .iter()
+        .map(|f| analyze_function(lines, f))
+        .collect();
+
+    let mut memo = HashMap::new();
+    let mut totals = Vec::new();
+    for (index, (function, info)) in functions.iter().zip(infos.iter()).enumerate() {
+        let total = worst_case(lines, index, functions, &infos, &mut Vec::new(), &mut memo);
+        totals.push(total);
+
+        let indirect = info.calls.iter().filter(|(_, t)| t.is_none()).count();
+        print!(
+            "{:<16} frame {:>3} bytes   worst case {}",
+            function.name(lines),
+            info.frame,
+            print_depth(total)
+        );
+        if indirect > 0 {
+            print!(" + {indirect} indirect call(s)");
+        }
+        if !info.unknown.is_empty() {
+            print!(", SP loaded at {} line(s)", info.unknown.len());
+        }
+        println!();
+    }
+
+    // main runs from reset, or from the start of the image when there is no vector table
+    let main = functions
+        .iter()
+        .position(|f| f.kind == FunctionKind::Vector(15))
+        .unwrap_or(0);
+    let isrs: Vec<usize> = (0..functions.len())
+        .filter(|&i| matches!(functions[i].kind, FunctionKind::Vector(v) if v != 15))
+        .collect();
+
+    let isr_totals: Option<Vec<u32>> = isrs
+        .iter()
+        .map(|&i| totals[i].map(|t| t + INTERRUPT_ENTRY))
+        .collect();
+    // interrupts only nest if a handler turns them back on
+    let nesting = isrs
+        .iter()
+        .any(|&i| enables_interrupts(lines, &functions[i]));
+    let interrupts = isr_totals.map(|totals| match nesting {
+        true => totals.iter().sum(),
+        false => totals.into_iter().max().unwrap_or(0),
+    });
+
+    println!();
+    println!(
+        "worst case: {} from {} + {} of interrupts{}",
+        print_depth(totals[main].zip(interrupts).map(|(a, b)| a + b)),
+        functions[main].name(lines),
+        print_depth(interrupts),
+        if nesting { " (nested)" } else { "" }
+    );
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    data::{reached_lines, DataItem},
+    functions::Function,
+    globals::{AddressMode, Instruction, Line, PC},
+};
+
+/*
+    Instructions are only counted where some function reaches them, the
+    rest of the image is whatever data.rs made of it, so a string doesn't
+    turn up as a handful of odd looking opcodes.
+*/
+
+#[derive(Default)]
+pub struct Stats {
+    pub opcodes: BTreeMap<String, usize>,
+    pub source_modes: BTreeMap<String, usize>,
+    pub dest_modes: BTreeMap<String, usize>,
+    pub byte_ops: usize,
+    pub word_ops: usize,
+    pub emulated: usize,
+    pub instructions: usize,
+    pub invalid: usize,
+    pub code_bytes: usize,
+    pub invalid_bytes: usize,
+    pub data_bytes: usize,
+    pub data_kinds: BTreeMap<String, usize>, // bytes of each kind of data
+    pub extension_words: [usize; 3],         // instructions with 0, 1 and 2 extension words
+}
+
+pub fn mnemonic(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Invalid => "(invalid)".to_owned(),
+        Instruction::JMP { condition, .. } => format!("{condition:?}"),
+        Instruction::ONE { opcode, b, .. } => format!("{opcode:?}{b}"),
+        Instruction::TWO { opcode, b, .. } => format!("{opcode:?}{b}"),
+        Instruction::TWO_BUT_WITH_A_SIGNED_WORD_I_HATE_RUST { opcode, b, .. } => {
+            format!("{opcode:?}{b}")
+        }
+        Instruction::PSEUDO { opcode, b, .. } => format!("{opcode:?}{b}"),
+    }
+}
+
+// spells the addressing mode the way the user's guide tables do
+fn mode_name(am: AddressMode, reg: u8) -> &'static str {
+    use AddressMode::*;
+    match am {
+        Direct => "Rn",
+        Indexed if reg == PC => "EDE (symbolic)",
+        Indexed => "x(Rn)",
+        Indirect => "@Rn",
+        IndirectIncrement if reg == PC => "#N",
+        IndirectIncrement => "@Rn+",
+        AbsoluteAddressing => "&EDE",
+        Const4 | Const8 | Const0 | Const1 | Const2 | ConstNeg1 => "#N (constant generator)",
+    }
+}
+
+impl Stats {
+    pub fn collect(lines: &[Line], functions: &[Function], data: &[DataItem]) -> Stats {
+        let mut stats = Stats::default();
+        for item in data {
+            stats.data_bytes += item.kind.bytes();
+            *stats
+                .data_kinds
+                .entry(item.kind.name().to_owned())
+                .or_default() += item.kind.bytes();
+        }
+
+        let reached = reached_lines(functions);
+        for (i, line) in lines.iter().enumerate() {
+            if !reached.contains(&i) {
+                continue;
+            }
+            let bytes = line.words.0.len() * 2;
+            if let Instruction::Invalid = line.raw {
+                stats.invalid += 1;
+                stats.invalid_bytes += bytes;
+                continue;
+            }
+
+            stats.instructions += 1;
+            stats.code_bytes += bytes;
+            stats.extension_words[(line.words.0.len() - 1).min(2)] += 1;
+            *stats
+                .opcodes
+                .entry(mnemonic(&line.instruction))
+                .or_default() += 1;
+            if let Instruction::PSEUDO { .. } = line.instruction {
+                stats.emulated += 1;
+            }
+
+            // addressing modes and width come from the real instruction, not the emulated one
+            match line.raw {
+                Instruction::ONE { b, dam, dest, .. } => {
+                    stats.count_width(b.0);
+                    *stats
+                        .dest_modes
+                        .entry(mode_name(dam, dest.0).to_owned())
+                        .or_default() += 1;
+                }
+                Instruction::TWO {
+                    b,
+                    sam,
+                    src,
+                    dam,
+                    dest,
+                    ..
+                } => {
+                    stats.count_width(b.0);
+                    *stats
+                        .source_modes
+                        .entry(mode_name(sam, src.0).to_owned())
+                        .or_default() += 1;
+                    *stats
+                        .dest_modes
+                        .entry(mode_name(dam, dest.0).to_owned())
+                        .or_default() += 1;
+                }
+                _ => (),
+            }
+        }
+        stats
+    }
+
+    fn count_width(&mut self, byte: bool) {
+        if byte {
+            self.byte_ops += 1;
+        } else {
+            self.word_ops += 1;
+        }
+    }
+
+    pub fn print(&self) {
+        println!("instructions:      {}", self.instructions);
+        println!("  emulated:        {}", self.emulated);
+        println!("  byte operations: {}", self.byte_ops);
+        println!("  word operations: {}", self.word_ops);
+        println!("code size:         {} bytes", self.code_bytes);
+        println!("data size:         {} bytes", self.data_bytes);
+        for (kind, bytes) in &self.data_kinds {
+            println!("  {kind:<16} {bytes}");
+        }
+        println!(
+            "invalid words:     {} ({} bytes)",
+            self.invalid, self.invalid_bytes
+        );
+        println!("extension words per instruction:");
+        for (count, instructions) in self.extension_words.iter().enumerate() {
+            println!("  {count}: {instructions}");
+        }
+        print_histogram("opcodes", &self.opcodes);
+        print_histogram("source addressing modes", &self.source_modes);
+        print_histogram("destination addressing modes", &self.dest_modes);
+    }
+}
+
+// most used first, ties in alphabetical order
+fn print_histogram(title: &str, map: &BTreeMap<String, usize>) {
+    let mut entries: Vec<(&String, &usize)> = map.iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(a.1));
+
+    println!("{title}:");
+    for (name, count) in entries {
+        println!("  {name:<24} {count}");
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, fs, io};
+
+use crate::{
+    emulator::{Machine, Step},
+    peripherals::Peripheral,
+};
+
+/*
+    How many times each instruction ran, either recorded by running the
+    emulator or read from a trace file captured on hardware.
+
+    Trace files are text, one PC per line in hex with an optional count
+    after it, written with an x so it can't be mistaken for anything else:
+
+        8000
+        8004 x12
+        # comments and blank lines are skipped
+
+    Any other field after the PC is ignored, so a log with a PC at the
+    start of every line loads as is, like mspdebug's "8004 4303 ..." where
+    the next field is the instruction word. Repeated PCs add up.
+    --save-trace writes the "pc xcount" form back out.
+*/
+
+#[derive(Default)]
+pub struct Trace {
+    pub counts: HashMap<u32, u64>, // CPU address -> times executed
+}
+
+impl Trace {
+    pub fn count(&self, pc: u32) -> u64 {
+        self.counts.get(&pc).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, pc: u32, count: u64) {
+        *self.counts.entry(pc).or_default() += count;
+    }
+
+    pub fn parse(text: &str) -> Result<Trace, String> {
+        let mut trace = Trace::default();
+        for (n, line) in text.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let pc = match fields.next() {
+                None => continue,
+                Some(field) if field.starts_with('#') => continue,
+                Some(field) => field.trim_start_matches("0x"),
+            };
+            let pc = u32::from_str_radix(pc, 16)
+                .map_err(|_| format!("line {}: {pc:?} isn't an address", n + 1))?;
+            let count = fields
+                .next()
+                .and_then(|field| field.strip_prefix('x')?.parse().ok())
+                .unwrap_or(1);
+            trace.add(pc, count);
+        }
+        Ok(trace)
+    }
+
+    pub fn load(path: &str) -> Result<Trace, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Trace::parse(&text).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut pcs: Vec<(&u32, &u64)> = self.counts.iter().collect();
+        pcs.sort();
+        pcs.iter()
+            .map(|(pc, count)| format!("{pc:04x} x{count}\n"))
+            .collect()
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    // run from reset for `steps` instructions, sleeping doesn't use any up
+    pub fn record(
+        bytes: &[u8],
+        base: u32,
+        steps: usize,
+        peripherals: Vec<Box<dyn Peripheral>>,
+    ) -> Trace {
+        let mut machine = Machine::with_peripherals(peripherals);
+        machine.load_image(bytes, base);
+        machine.reset();
+
+        let mut trace = Trace::default();
+        machine.run(steps, |_, stepped| match stepped.step {
+            // a reset comes after the instruction that caused it
+            Step::Ran | Step::Reset => trace.add(stepped.pc.into(), 1),
+            Step::Interrupt | Step::Sleeping => (),
+        });
+        trace
+    }
+}
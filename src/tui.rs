@@ -0,0 +1,471 @@
+use std::{
+    collections::HashMap,
+    io::{stdin, stdout, Read, Write},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    annotations::Annotations,
+    data::{directive, immediate_address, reached_lines, DataItem},
+    flow::{call_target, jump_target},
+    functions::Function,
+    globals::Line,
+};
+
+/*
+    --tui: the listing in a terminal you can move around in. No curses,
+    just ANSI escapes and `stty` to get the terminal out of line mode,
+    which is there on everything we'd run this on.
+
+    Browser has all the state and turns keys into moves, the loop at the
+    bottom only reads keys and draws what render() gives it.
+
+        j/k, arrows, PgUp/PgDn   move
+        enter, l, right          follow the branch, call or data reference
+        backspace, h, left       back to where the last follow started
+        x                        who references this line
+        g                        go to an address (hex) or function name
+        q                        quit
+*/
+
+const HEX_ROWS: usize = 3; // used words plus two rows of dump
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Left,
+    Right,
+    Enter,
+    Backspace,
+    Escape,
+    Char(char),
+}
+
+struct Row {
+    address: u32,
+    text: String,
+    line: Option<usize>, // None for function headers and data
+}
+
+enum Mode {
+    Browse,
+    Goto(String),
+    Xrefs { from: Vec<u32>, selected: usize },
+    Message(String),
+}
+
+pub struct Browser<'l> {
+    rows: Vec<Row>,
+    lines: &'l [Line],
+    bytes: &'l [u8],
+    base: u32, // CPU address of bytes[0]
+    names: HashMap<String, u32>,
+    xrefs: HashMap<u32, Vec<u32>>, // address -> lines that branch, call or point there
+    pub cursor: usize,
+    pub top: usize,
+    back: Vec<usize>,
+    mode: Mode,
+}
+
+// where a line sends you when followed
+fn reference(line: &Line) -> Option<u32> {
+    jump_target(line)
+        .or_else(|| call_target(line))
+        .or_else(|| line.targets.first().copied())
+        .map(|a| a.0)
+        .or_else(|| immediate_address(&line.instruction))
+}
+
+impl<'l> Browser<'l> {
+    pub fn new(
+        lines: &'l [Line],
+        functions: &[Function],
+        data: &[DataItem],
+        bytes: &'l [u8],
+        base: u32,
+        notes: &Annotations,
+    ) -> Browser<'l> {
+        let reached = reached_lines(functions);
+        let starts: HashMap<usize, &Function> = functions.iter().map(|f| (f.start, f)).collect();
+
+        let mut rows = Vec::new();
+        let mut entries: Vec<(u32, Option<usize>, Option<&DataItem>)> = (0..lines.len())
+            .filter(|i| reached.contains(i))
+            .map(|i| (lines[i].address.0, Some(i), None))
+            .chain(data.iter().map(|item| (item.address.0, None, Some(item))))
+            .collect();
+        entries.sort_by_key(|(address, ..)| *address);
+        for (address, line, item) in entries {
+            if let Some(item) = item {
+                rows.push(Row {
+                    address,
+                    text: format!("{address:04x}   {}", directive(&item.kind)),
+                    line: None,
+                });
+                continue;
+            }
+            let i = line.unwrap();
+            if let Some(function) = starts.get(&i) {
+                rows.push(Row {
+                    address,
+                    text: format!("; {} ({:?})", function.name(lines), function.kind),
+                    line: None,
+                });
+            } else if let Some(label) = notes.label(&lines[i]) {
+                rows.push(Row {
+                    address,
+                    text: format!("{label}:"),
+                    line: None,
+                });
+            }
+            let mut comment = match reference(&lines[i]) {
+                Some(target) => format!("   ; -> {target:#06x}"),
+                None => "".to_owned(),
+            };
+            if let Some(text) = notes.comment(&lines[i]) {
+                comment += &format!("   ; {text}");
+            }
+            rows.push(Row {
+                address,
+                text: format!(
+                    "{address:04x}   {:<14} {}{comment}",
+                    lines[i].words.to_string(),
+                    lines[i].instruction
+                ),
+                line: Some(i),
+            });
+        }
+
+        let mut xrefs: HashMap<u32, Vec<u32>> = HashMap::new();
+        for line in lines {
+            let targets = jump_target(line)
+                .into_iter()
+                .chain(call_target(line))
+                .chain(line.targets.iter().copied())
+                .map(|a| a.0)
+                .chain(immediate_address(&line.instruction));
+            for target in targets {
+                xrefs.entry(target).or_default().push(line.address.0);
+            }
+        }
+        for from in xrefs.values_mut() {
+            from.sort();
+            from.dedup();
+        }
+
+        Browser {
+            rows,
+            lines,
+            bytes,
+            base,
+            names: functions
+                .iter()
+                .map(|f| (f.name(lines), lines[f.start].address.0))
+                .collect(),
+            xrefs,
+            cursor: 0,
+            top: 0,
+            back: Vec::new(),
+            mode: Mode::Browse,
+        }
+    }
+
+    // first row at or after an address, code before headers doesn't matter here
+    fn row_at(&self, address: u32) -> Option<usize> {
+        if address < self.base {
+            return None;
+        }
+        let row = self.rows.partition_point(|row| row.address < address);
+        (row < self.rows.len()).then_some(row)
+    }
+
+    fn code_row_at(&self, address: u32) -> Option<usize> {
+        let row = self.row_at(address)?;
+        (row..self.rows.len())
+            .find(|&r| self.rows[r].line.is_some() || self.rows[r].address > address)
+    }
+
+    pub fn address(&self) -> u32 {
+        self.rows.get(self.cursor).map_or(0, |row| row.address)
+    }
+
+    fn jump(&mut self, row: usize) {
+        self.back.push(self.cursor);
+        self.cursor = row;
+    }
+
+    fn follow(&mut self) {
+        let target = self.rows[self.cursor]
+            .line
+            .and_then(|i| reference(&self.lines[i]));
+        match target.and_then(|t| self.code_row_at(t)) {
+            Some(row) => self.jump(row),
+            None => self.mode = Mode::Message("nothing to follow".to_owned()),
+        }
+    }
+
+    // a name, or a hex address either as a CPU address or a file offset
+    fn goto(&mut self, text: &str) {
+        let address = self.names.get(text).copied().or_else(|| {
+            let value = u32::from_str_radix(text.trim_start_matches("0x"), 16).ok()?;
+            Some(match value < self.base {
+                true => value + self.base,
+                false => value,
+            })
+        });
+        match address.and_then(|a| self.code_row_at(a)) {
+            Some(row) => self.jump(row),
+            None => self.mode = Mode::Message(format!("no {text}")),
+        }
+    }
+
+    // false once it's time to quit
+    pub fn key(&mut self, key: Key, height: usize) -> bool {
+        let last = self.rows.len().saturating_sub(1);
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Goto(mut text) => match key {
+                Key::Enter => self.goto(text.trim()),
+                Key::Escape => (),
+                Key::Backspace => {
+                    text.pop();
+                    self.mode = Mode::Goto(text);
+                }
+                Key::Char(c) => {
+                    text.push(c);
+                    self.mode = Mode::Goto(text);
+                }
+                _ => self.mode = Mode::Goto(text),
+            },
+            Mode::Xrefs { from, selected } => match key {
+                Key::Up | Key::Char('k') => {
+                    let selected = selected.saturating_sub(1);
+                    self.mode = Mode::Xrefs { from, selected };
+                }
+                Key::Down | Key::Char('j') => {
+                    let selected = (selected + 1).min(from.len() - 1);
+                    self.mode = Mode::Xrefs { from, selected };
+                }
+                Key::Enter | Key::Right | Key::Char('l') => {
+                    if let Some(row) = self.code_row_at(from[selected]) {
+                        self.jump(row);
+                    }
+                }
+                _ => (),
+            },
+            Mode::Browse | Mode::Message(_) => match key {
+                Key::Char('q') => return false,
+                Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+                Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+                Key::PageUp | Key::Char('b') => self.cursor = self.cursor.saturating_sub(height),
+                Key::PageDown | Key::Char(' ') => self.cursor = (self.cursor + height).min(last),
+                Key::Home => self.cursor = 0,
+                Key::End | Key::Char('G') => self.cursor = last,
+                Key::Enter | Key::Right | Key::Char('l') => self.follow(),
+                Key::Backspace | Key::Left | Key::Char('h') => match self.back.pop() {
+                    Some(row) => self.cursor = row,
+                    None => self.mode = Mode::Message("nowhere to go back to".to_owned()),
+                },
+                Key::Char('g') => self.mode = Mode::Goto(String::new()),
+                Key::Char('x') => match self.xrefs.get(&self.address()) {
+                    Some(from) => {
+                        self.mode = Mode::Xrefs {
+                            from: from.clone(),
+                            selected: 0,
+                        }
+                    }
+                    None => self.mode = Mode::Message("no references".to_owned()),
+                },
+                _ => (),
+            },
+        }
+        true
+    }
+
+    fn hex_pane(&self) -> Vec<String> {
+        let mut pane = match self.rows[self.cursor].line {
+            Some(i) => vec![format!("words  {}", self.lines[i].words)],
+            None => vec!["".to_owned()],
+        };
+        let start = (self.address().saturating_sub(self.base) & !0xf) as usize;
+        for offset in [start, start + 16] {
+            let bytes = match self.bytes.get(offset..(offset + 16).min(self.bytes.len())) {
+                Some(bytes) if !bytes.is_empty() => bytes,
+                _ => break,
+            };
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect();
+            pane.push(format!(
+                "{:04x}   {:<47}  {ascii}",
+                self.base + offset as u32,
+                hex.join(" ")
+            ));
+        }
+        pane
+    }
+
+    // the whole screen, listing on top, then the hex pane and a status line
+    pub fn render(&mut self, height: usize, width: usize) -> String {
+        let listing = height.saturating_sub(HEX_ROWS + 1).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + listing {
+            self.top = self.cursor + 1 - listing;
+        }
+
+        let clip = |text: &str| text.chars().take(width).collect::<String>();
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        for row in self.top..self.top + listing {
+            match self.rows.get(row) {
+                Some(r) if row == self.cursor => {
+                    screen += &format!("\x1b[7m{}\x1b[0m", clip(&r.text))
+                }
+                Some(r) => screen += &clip(&r.text),
+                None => (),
+            }
+            screen += "\r\n";
+        }
+
+        let mut pane = self.hex_pane();
+        pane.resize(HEX_ROWS, String::new());
+        for line in pane {
+            screen += &format!("\x1b[2m{}\x1b[0m\r\n", clip(&line));
+        }
+
+        let status = match &self.mode {
+            Mode::Browse => {
+                "q quit  j/k move  enter follow  backspace back  x xrefs  g goto".to_owned()
+            }
+            Mode::Goto(text) => format!("goto: {text}"),
+            Mode::Message(message) => message.clone(),
+            Mode::Xrefs { .. } => "references, enter to go there, esc to close".to_owned(),
+        };
+        screen += &format!("\x1b[7m{:<width$}\x1b[0m", clip(&status));
+
+        // the popup goes over the top right of the listing
+        if let Mode::Xrefs { from, selected } = &self.mode {
+            for (n, &address) in from.iter().enumerate().take(listing) {
+                let text = match self.code_row_at(address) {
+                    Some(row) => self.rows[row].text.clone(),
+                    None => format!("{address:04x}"),
+                };
+                let text: String = text.chars().take(width / 2).collect();
+                let style = if n == *selected {
+                    "\x1b[7m"
+                } else {
+                    "\x1b[44m"
+                };
+                screen += &format!(
+                    "\x1b[{};{}H{style}{text:<w$}\x1b[0m",
+                    n + 1,
+                    width / 2,
+                    w = width / 2
+                );
+            }
+        }
+        screen
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// one read's worth of input as keys, escape sequences included
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, used) = match &bytes[i..] {
+            [0x1b, b'[', b'A', ..] => (Key::Up, 3),
+            [0x1b, b'[', b'B', ..] => (Key::Down, 3),
+            [0x1b, b'[', b'C', ..] => (Key::Right, 3),
+            [0x1b, b'[', b'D', ..] => (Key::Left, 3),
+            [0x1b, b'[', b'H', ..] => (Key::Home, 3),
+            [0x1b, b'[', b'F', ..] => (Key::End, 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Key::PageUp, 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Key::PageDown, 4),
+            [0x1b, ..] => (Key::Escape, 1),
+            [b'\r' | b'\n', ..] => (Key::Enter, 1),
+            [0x7f | 0x08, ..] => (Key::Backspace, 1),
+            [0x03, ..] => (Key::Char('q'), 1), // ctrl-c, raw mode doesn't turn it into a signal
+            [byte, ..] => (Key::Char(*byte as char), 1),
+            [] => break,
+        };
+        keys.push(key);
+        i += used;
+    }
+    keys
+}
+
+pub fn run_tui(
+    lines: &[Line],
+    functions: &[Function],
+    data: &[DataItem],
+    bytes: &[u8],
+    base: u32,
+    notes: &Annotations,
+) {
+    let mut browser = Browser::new(lines, functions, data, bytes, base, notes);
+    if browser.rows.is_empty() {
+        eprintln!("nothing to show");
+        return;
+    }
+    let saved = match stty(&["-g"]) {
+        Some(saved) => saved,
+        None => {
+            eprintln!("--tui needs a terminal");
+            return;
+        }
+    };
+    stty(&["raw", "-echo"]);
+    let mut out = stdout();
+    let _ = write!(out, "\x1b[?1049h\x1b[?25l"); // alternate screen, no cursor
+
+    let mut input = stdin().lock();
+    let mut buffer = [0; 16];
+    'running: loop {
+        let (height, width) = stty(&["size"])
+            .and_then(|size| {
+                let (rows, cols) = size.split_once(' ')?;
+                Some((rows.parse().ok()?, cols.parse().ok()?))
+            })
+            .filter(|&(rows, cols)| rows > 0 && cols > 0) // no size set, a pty from `script`
+            .unwrap_or((24, 80));
+        let _ = out.write_all(browser.render(height, width).as_bytes());
+        let _ = out.flush();
+
+        let read = match input.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        let page = height.saturating_sub(HEX_ROWS + 1);
+        for key in parse_keys(&buffer[..read]) {
+            if !browser.key(key, page) {
+                break 'running;
+            }
+        }
+    }
+
+    let _ = write!(out, "\x1b[?25h\x1b[?1049l");
+    let _ = out.flush();
+    stty(&[&saved]);
+}